@@ -0,0 +1,20 @@
+//! KWarpd Clock
+//!
+//! Time source for the session's timers and physics, so tests can step time
+//! instead of sleeping
+
+use std::time::Instant;
+
+/// Source of the current time
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// The system's monotonic clock
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
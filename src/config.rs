@@ -3,9 +3,13 @@
 //! Handles loading and parsing configuration from ~/.config/kwarpd/kwarpd.conf
 
 use anyhow::{Context, Result};
+use nix::errno::Errno;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
 use serde::Deserialize;
+use std::ffi::OsString;
 use std::fs;
-use std::path::PathBuf;
+use std::os::fd::{AsFd, BorrowedFd};
+use std::path::{Path, PathBuf};
 
 /// Modifier keys that can be combined with other keys
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
@@ -55,12 +59,30 @@ impl KeyBinding {
     }
 }
 
+/// Mouse buttons that can be bound to keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    Side,
+    Extra,
+    Forward,
+    Back,
+}
+
 /// Mouse buttons configuration
+///
+/// Empty strings leave a button unbound.
 #[derive(Debug, Clone)]
 pub struct MouseButtons {
     pub left: String,
     pub middle: String,
     pub right: String,
+    pub side: String,
+    pub extra: String,
+    pub forward: String,
+    pub back: String,
 }
 
 impl Default for MouseButtons {
@@ -69,6 +91,179 @@ impl Default for MouseButtons {
             left: "m".to_string(),
             middle: ",".to_string(),
             right: ".".to_string(),
+            side: String::new(),
+            extra: String::new(),
+            forward: String::new(),
+            back: String::new(),
+        }
+    }
+}
+
+impl MouseButtons {
+    /// Get the button bound to a key, if any
+    pub fn button_for(&self, key: &str) -> Option<MouseButton> {
+        if key.is_empty() {
+            return None;
+        }
+        [
+            (&self.left, MouseButton::Left),
+            (&self.middle, MouseButton::Middle),
+            (&self.right, MouseButton::Right),
+            (&self.side, MouseButton::Side),
+            (&self.extra, MouseButton::Extra),
+            (&self.forward, MouseButton::Forward),
+            (&self.back, MouseButton::Back),
+        ]
+        .into_iter()
+        .find(|(binding, _)| binding.as_str() == key)
+        .map(|(_, button)| button)
+    }
+}
+
+/// Held-key behaviours that repeat on kwarpd's own timer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepeatAction {
+    /// Holding a mouse button key clicks repeatedly
+    Click,
+    /// Holding backspace in hint or grid mode keeps undoing
+    Backspace,
+}
+
+impl RepeatAction {
+    /// Parse a repeat action name
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "click" => Ok(RepeatAction::Click),
+            "backspace" => Ok(RepeatAction::Backspace),
+            _ => anyhow::bail!("Unknown repeat action: {} (expected click or backspace)", s),
+        }
+    }
+}
+
+/// How pointer events reach the compositor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerBackendKind {
+    /// uinput if /dev/uinput is writable, otherwise the first Wayland protocol available
+    Auto,
+    /// Virtual input devices through /dev/uinput
+    Uinput,
+    /// KWin org_kde_kwin_fake_input
+    Kde,
+    /// wlroots zwlr_virtual_pointer_v1
+    Wlr,
+}
+
+impl PointerBackendKind {
+    /// Parse a pointer backend name
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(PointerBackendKind::Auto),
+            "uinput" => Ok(PointerBackendKind::Uinput),
+            "kde" => Ok(PointerBackendKind::Kde),
+            "wlr" => Ok(PointerBackendKind::Wlr),
+            _ => anyhow::bail!(
+                "Unknown pointer backend: {} (expected auto, uinput, kde or wlr)",
+                s
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for PointerBackendKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            PointerBackendKind::Auto => "auto",
+            PointerBackendKind::Uinput => "uinput",
+            PointerBackendKind::Kde => "kde",
+            PointerBackendKind::Wlr => "wlr",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Screen corner or edge the mode badge is anchored to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BadgePosition {
+    TopLeft,
+    Top,
+    TopRight,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl BadgePosition {
+    /// Parse a badge position; "none" hides the badge
+    pub fn parse(s: &str) -> Result<Option<Self>> {
+        Ok(Some(match s {
+            "none" => return Ok(None),
+            "top-left" => BadgePosition::TopLeft,
+            "top" => BadgePosition::Top,
+            "top-right" => BadgePosition::TopRight,
+            "bottom-left" => BadgePosition::BottomLeft,
+            "bottom" => BadgePosition::Bottom,
+            "bottom-right" => BadgePosition::BottomRight,
+            _ => anyhow::bail!(
+                "Unknown badge position: {} (expected top-left, top, top-right, \
+                 bottom-left, bottom, bottom-right or none)",
+                s
+            ),
+        }))
+    }
+}
+
+/// Selects an input device for `include_devices` / `exclude_devices`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceMatcher {
+    /// Exact device name, as shown by `kwarpd list-devices`
+    Name(String),
+    /// USB/Bluetooth vendor and product ID, written "046d:c52b" or "id:046d:c52b"
+    Id { vendor: u16, product: u16 },
+    /// Device node or a symlink to it, e.g. under /dev/input/by-id
+    Path(PathBuf),
+}
+
+impl DeviceMatcher {
+    /// Parse a matcher: paths start with "/", "id:" and "name:" pick the
+    /// kind explicitly, a bare "vvvv:pppp" in hex is an ID and anything else
+    /// is a device name
+    ///
+    /// Names that happen to look like an ID, e.g. "Cafe:Beef", need the
+    /// "name:" prefix. A malformed "id:" is an error rather than a matcher
+    /// that never matches.
+    pub fn parse(s: &str) -> Result<Self> {
+        if s.starts_with('/') {
+            return Ok(DeviceMatcher::Path(PathBuf::from(s)));
+        }
+        if let Some(name) = s.strip_prefix("name:") {
+            return Ok(DeviceMatcher::Name(name.to_string()));
+        }
+        if let Some(id) = s.strip_prefix("id:") {
+            return Self::parse_id(id)
+                .with_context(|| format!("Invalid device ID: {} (expected id:vvvv:pppp in hex)", id));
+        }
+        Ok(Self::parse_id(s).unwrap_or_else(|| DeviceMatcher::Name(s.to_string())))
+    }
+
+    fn parse_id(s: &str) -> Option<Self> {
+        let (vendor, product) = s.split_once(':')?;
+        let is_id_part = |part: &str| part.len() == 4 && part.chars().all(|c| c.is_ascii_hexdigit());
+        if !is_id_part(vendor) || !is_id_part(product) {
+            return None;
+        }
+        Some(DeviceMatcher::Id {
+            vendor: u16::from_str_radix(vendor, 16).ok()?,
+            product: u16::from_str_radix(product, 16).ok()?,
+        })
+    }
+}
+
+impl std::fmt::Display for DeviceMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceMatcher::Name(name) => write!(f, "{:?}", name),
+            DeviceMatcher::Id { vendor, product } => write!(f, "{:04x}:{:04x}", vendor, product),
+            DeviceMatcher::Path(path) => write!(f, "{}", path.display()),
         }
     }
 }
@@ -80,12 +275,21 @@ struct RawConfig {
     // Activation keys
     hint_activation_key: Option<String>,
     activation_key: Option<String>,
+    grid_activation_key: Option<String>,
 
     // Mode control
     exit: Option<String>,
     drag: Option<String>,
     copy_and_exit: Option<String>,
+    copy_chord: Option<String>,
     hint: Option<String>,
+    grid: Option<String>,
+    passthrough: Option<bool>,
+
+    // Key repeat (space-separated actions)
+    repeat: Option<String>,
+    repeat_delay: Option<u32>,
+    repeat_rate: Option<u32>,
 
     // Movement modifiers
     accelerator: Option<String>,
@@ -93,6 +297,10 @@ struct RawConfig {
 
     // Mouse buttons (space-separated)
     buttons: Option<String>,
+    button_side: Option<String>,
+    button_extra: Option<String>,
+    button_forward: Option<String>,
+    button_back: Option<String>,
 
     // Movement keys
     left: Option<String>,
@@ -103,10 +311,28 @@ struct RawConfig {
     // Scrolling
     scroll_down: Option<String>,
     scroll_up: Option<String>,
+    scroll_left: Option<String>,
+    scroll_right: Option<String>,
+
+    // Keyboard layout
+    physical_keys: Option<bool>,
+    xkb_rules: Option<String>,
+    xkb_model: Option<String>,
+    xkb_layout: Option<String>,
+    xkb_variant: Option<String>,
+    xkb_options: Option<String>,
+
+    // Device selection
+    include_devices: Option<Vec<String>>,
+    exclude_devices: Option<Vec<String>>,
+
+    // Output
+    pointer_backend: Option<String>,
 
     // Visual settings
     cursor_color: Option<String>,
     cursor_size: Option<u32>,
+    badge_position: Option<String>,
 
     // Movement physics
     speed: Option<u32>,
@@ -120,11 +346,24 @@ struct RawConfig {
     hint_size: Option<u32>,
     hint_exit: Option<String>,
 
+    // Grid mode settings
+    grid_keys: Option<String>,
+    grid_rows: Option<u32>,
+    grid_columns: Option<u32>,
+    grid_max_depth: Option<u32>,
+    grid_confirm: Option<String>,
+    grid_exit: Option<String>,
+    grid_color: Option<String>,
+    grid_border_color: Option<String>,
+
     // Scroll physics
     scroll_speed: Option<u32>,
     scroll_max_speed: Option<u32>,
     scroll_acceleration: Option<u32>,
     scroll_deceleration: Option<i32>,
+
+    // Safety
+    idle_timeout: Option<u32>,
 }
 
 /// Parsed and validated configuration
@@ -133,12 +372,26 @@ pub struct Config {
     // Activation keys
     pub hint_activation_key: KeyBinding,
     pub activation_key: KeyBinding,
+    pub grid_activation_key: KeyBinding,
 
     // Mode control
     pub exit: String,
     pub drag: String,
     pub copy_and_exit: String,
+    /// Chord typed by copy_and_exit after releasing the keyboard
+    pub copy_chord: KeyBinding,
     pub hint: String,
+    pub grid: String,
+    /// Forward keys without a normal mode binding to the focused window
+    pub passthrough: bool,
+
+    // Key repeat
+    /// Actions that repeat while their key is held
+    pub repeat: Vec<RepeatAction>,
+    /// Milliseconds before a held key starts repeating
+    pub repeat_delay: u32,
+    /// Repeats per second (0 = never)
+    pub repeat_rate: u32,
 
     // Movement modifiers
     pub accelerator: String,
@@ -156,10 +409,35 @@ pub struct Config {
     // Scrolling keys
     pub scroll_down: String,
     pub scroll_up: String,
+    pub scroll_left: String,
+    pub scroll_right: String,
+
+    // Keyboard layout
+    /// Match bindings against physical US-QWERTY key positions instead of the xkb layout
+    pub physical_keys: bool,
+    /// xkb RMLVO names; without a layout, the session layout from KDE or the
+    /// system keyboard config is used, then the libxkbcommon default
+    pub xkb_rules: String,
+    pub xkb_model: String,
+    pub xkb_layout: String,
+    pub xkb_variant: String,
+    pub xkb_options: String,
+
+    // Device selection
+    /// When non-empty, only these devices are grabbed, keyboard or not
+    pub include_devices: Vec<DeviceMatcher>,
+    /// Devices never grabbed; wins over include_devices
+    pub exclude_devices: Vec<DeviceMatcher>,
+
+    // Output
+    /// Read at startup only; changing it needs a restart
+    pub pointer_backend: PointerBackendKind,
 
     // Visual settings
     pub cursor_color: u32, // RGBA
     pub cursor_size: u32,
+    /// Where the mode badge is shown, `None` to hide it
+    pub badge_position: Option<BadgePosition>,
 
     // Movement physics
     pub speed: u32,
@@ -173,11 +451,27 @@ pub struct Config {
     pub hint_size: u32,
     pub hint_exit: String,
 
+    // Grid mode settings
+    /// One key per cell, row by row
+    pub grid_keys: String,
+    pub grid_rows: u32,
+    pub grid_columns: u32,
+    /// Number of selections after which the cursor is warped automatically
+    pub grid_max_depth: u32,
+    pub grid_confirm: String,
+    pub grid_exit: String,
+    pub grid_color: u32, // RGBA
+    pub grid_border_color: u32, // RGBA
+
     // Scroll physics
     pub scroll_speed: u32,
     pub scroll_max_speed: u32,
     pub scroll_acceleration: u32,
     pub scroll_deceleration: i32,
+
+    // Safety
+    /// Seconds without input after which an active mode exits (0 = never)
+    pub idle_timeout: u32,
 }
 
 impl Default for Config {
@@ -185,10 +479,17 @@ impl Default for Config {
         Self {
             hint_activation_key: KeyBinding::parse("A-M-x").unwrap(),
             activation_key: KeyBinding::parse("A-M-c").unwrap(),
+            grid_activation_key: KeyBinding::parse("A-M-g").unwrap(),
             exit: "esc".to_string(),
             drag: "v".to_string(),
             copy_and_exit: "c".to_string(),
+            copy_chord: KeyBinding::parse("C-c").unwrap(),
             hint: "x".to_string(),
+            grid: "g".to_string(),
+            passthrough: false,
+            repeat: vec![RepeatAction::Backspace],
+            repeat_delay: 400,
+            repeat_rate: 25,
             accelerator: "a".to_string(),
             decelerator: "d".to_string(),
             buttons: MouseButtons::default(),
@@ -198,8 +499,20 @@ impl Default for Config {
             right: "l".to_string(),
             scroll_down: "e".to_string(),
             scroll_up: "r".to_string(),
+            scroll_left: "w".to_string(),
+            scroll_right: "t".to_string(),
+            physical_keys: false,
+            xkb_rules: String::new(),
+            xkb_model: String::new(),
+            xkb_layout: String::new(),
+            xkb_variant: String::new(),
+            xkb_options: String::new(),
+            include_devices: Vec::new(),
+            exclude_devices: Vec::new(),
+            pointer_backend: PointerBackendKind::Auto,
             cursor_color: 0xFF4500FF, // #FF4500 (OrangeRed) with full alpha
             cursor_size: 7,
+            badge_position: Some(BadgePosition::TopRight),
             speed: 220,
             max_speed: 1600,
             decelerator_speed: 50,
@@ -208,10 +521,19 @@ impl Default for Config {
             hint_chars: "abcdefghijklmnopqrstuvwxyz".to_string(),
             hint_size: 20,
             hint_exit: "esc".to_string(),
+            grid_keys: "uijk".to_string(),
+            grid_rows: 2,
+            grid_columns: 2,
+            grid_max_depth: 8,
+            grid_confirm: "space".to_string(),
+            grid_exit: "esc".to_string(),
+            grid_color: 0x1C1C1EB0, // Translucent dark gray
+            grid_border_color: 0xFF4500FF, // OrangeRed
             scroll_speed: 300,
             scroll_max_speed: 9000,
             scroll_acceleration: 1600,
             scroll_deceleration: -3400,
+            idle_timeout: 0,
         }
     }
 }
@@ -226,10 +548,10 @@ impl Config {
     pub fn load() -> Result<Self> {
         let path = Self::default_path();
 
-        if let Some(ref p) = path {
-            if p.exists() {
-                return Self::load_from_file(p);
-            }
+        if let Some(ref p) = path
+            && p.exists()
+        {
+            return Self::load_from_file(p);
         }
 
         log::info!("No config file found, using defaults");
@@ -260,12 +582,21 @@ impl Config {
             config.activation_key = KeyBinding::parse(s)
                 .with_context(|| format!("Invalid activation_key: {}", s))?;
         }
+        if let Some(ref s) = raw.grid_activation_key {
+            config.grid_activation_key = KeyBinding::parse(s)
+                .with_context(|| format!("Invalid grid_activation_key: {}", s))?;
+        }
+        if let Some(ref s) = raw.copy_chord {
+            config.copy_chord = KeyBinding::parse(s)
+                .with_context(|| format!("Invalid copy_chord: {}", s))?;
+        }
 
         // Simple string options
         if let Some(s) = raw.exit { config.exit = s; }
         if let Some(s) = raw.drag { config.drag = s; }
         if let Some(s) = raw.copy_and_exit { config.copy_and_exit = s; }
         if let Some(s) = raw.hint { config.hint = s; }
+        if let Some(s) = raw.grid { config.grid = s; }
         if let Some(s) = raw.accelerator { config.accelerator = s; }
         if let Some(s) = raw.decelerator { config.decelerator = s; }
         if let Some(s) = raw.left { config.left = s; }
@@ -274,20 +605,63 @@ impl Config {
         if let Some(s) = raw.right { config.right = s; }
         if let Some(s) = raw.scroll_down { config.scroll_down = s; }
         if let Some(s) = raw.scroll_up { config.scroll_up = s; }
+        if let Some(s) = raw.scroll_left { config.scroll_left = s; }
+        if let Some(s) = raw.scroll_right { config.scroll_right = s; }
         if let Some(s) = raw.hint_chars { config.hint_chars = s; }
         if let Some(s) = raw.hint_exit { config.hint_exit = s; }
+        if let Some(s) = raw.grid_keys { config.grid_keys = s; }
+        if let Some(s) = raw.grid_confirm { config.grid_confirm = s; }
+        if let Some(s) = raw.grid_exit { config.grid_exit = s; }
+        if let Some(s) = raw.xkb_rules { config.xkb_rules = s; }
+        if let Some(s) = raw.xkb_model { config.xkb_model = s; }
+        if let Some(s) = raw.xkb_layout { config.xkb_layout = s; }
+        if let Some(s) = raw.xkb_variant { config.xkb_variant = s; }
+        if let Some(s) = raw.xkb_options { config.xkb_options = s; }
+        if let Some(v) = raw.include_devices {
+            config.include_devices = v
+                .iter()
+                .map(|s| DeviceMatcher::parse(s))
+                .collect::<Result<_>>()
+                .context("Invalid include_devices")?;
+        }
+        if let Some(v) = raw.exclude_devices {
+            config.exclude_devices = v
+                .iter()
+                .map(|s| DeviceMatcher::parse(s))
+                .collect::<Result<_>>()
+                .context("Invalid exclude_devices")?;
+        }
+        if let Some(ref s) = raw.pointer_backend {
+            config.pointer_backend = PointerBackendKind::parse(s)
+                .with_context(|| format!("Invalid pointer_backend: {}", s))?;
+        }
+        if let Some(v) = raw.physical_keys { config.physical_keys = v; }
+        if let Some(v) = raw.passthrough { config.passthrough = v; }
+        if let Some(v) = raw.repeat_delay { config.repeat_delay = v; }
+        if let Some(v) = raw.repeat_rate { config.repeat_rate = v; }
+
+        // Parse repeat actions (space-separated: "click backspace")
+        if let Some(ref s) = raw.repeat {
+            config.repeat = s
+                .split_whitespace()
+                .map(RepeatAction::parse)
+                .collect::<Result<_>>()
+                .with_context(|| format!("Invalid repeat: {}", s))?;
+        }
 
         // Parse buttons (space-separated: "m , .")
         if let Some(ref s) = raw.buttons {
             let parts: Vec<&str> = s.split_whitespace().collect();
             if parts.len() >= 3 {
-                config.buttons = MouseButtons {
-                    left: parts[0].to_string(),
-                    middle: parts[1].to_string(),
-                    right: parts[2].to_string(),
-                };
+                config.buttons.left = parts[0].to_string();
+                config.buttons.middle = parts[1].to_string();
+                config.buttons.right = parts[2].to_string();
             }
         }
+        if let Some(s) = raw.button_side { config.buttons.side = s; }
+        if let Some(s) = raw.button_extra { config.buttons.extra = s; }
+        if let Some(s) = raw.button_forward { config.buttons.forward = s; }
+        if let Some(s) = raw.button_back { config.buttons.back = s; }
 
         // Parse cursor color (#RRGGBB -> RGBA)
         if let Some(ref s) = raw.cursor_color {
@@ -295,8 +669,22 @@ impl Config {
                 .with_context(|| format!("Invalid cursor_color: {}", s))?;
         }
 
+        // Parse grid colors
+        if let Some(ref s) = raw.grid_color {
+            config.grid_color = parse_color(s)
+                .with_context(|| format!("Invalid grid_color: {}", s))?;
+        }
+        if let Some(ref s) = raw.grid_border_color {
+            config.grid_border_color = parse_color(s)
+                .with_context(|| format!("Invalid grid_border_color: {}", s))?;
+        }
+
         // Numeric options
         if let Some(v) = raw.cursor_size { config.cursor_size = v; }
+        if let Some(ref s) = raw.badge_position {
+            config.badge_position = BadgePosition::parse(s)
+                .with_context(|| format!("Invalid badge_position: {}", s))?;
+        }
         if let Some(v) = raw.speed { config.speed = v; }
         if let Some(v) = raw.max_speed { config.max_speed = v; }
         if let Some(v) = raw.decelerator_speed { config.decelerator_speed = v; }
@@ -307,9 +695,131 @@ impl Config {
         if let Some(v) = raw.scroll_max_speed { config.scroll_max_speed = v; }
         if let Some(v) = raw.scroll_acceleration { config.scroll_acceleration = v; }
         if let Some(v) = raw.scroll_deceleration { config.scroll_deceleration = v; }
+        if let Some(v) = raw.idle_timeout { config.idle_timeout = v; }
+        if let Some(v) = raw.grid_rows { config.grid_rows = v; }
+        if let Some(v) = raw.grid_columns { config.grid_columns = v; }
+        if let Some(v) = raw.grid_max_depth { config.grid_max_depth = v; }
 
+        config.validate()?;
         Ok(config)
     }
+
+    /// Check whether a key name is bound in normal mode
+    pub fn is_normal_binding(&self, key: &str) -> bool {
+        if self.buttons.button_for(key).is_some() {
+            return true;
+        }
+        [
+            &self.exit,
+            &self.hint,
+            &self.grid,
+            &self.drag,
+            &self.copy_and_exit,
+            &self.accelerator,
+            &self.decelerator,
+            &self.left,
+            &self.down,
+            &self.up,
+            &self.right,
+            &self.scroll_down,
+            &self.scroll_up,
+            &self.scroll_left,
+            &self.scroll_right,
+        ]
+        .iter()
+        .any(|binding| binding.as_str() == key)
+    }
+
+    /// Check option combinations that parse fine on their own but can't work together
+    pub fn validate(&self) -> Result<()> {
+        if self.hint_chars.is_empty() {
+            anyhow::bail!("hint_chars must not be empty");
+        }
+
+        // Every grid cell needs a key
+        if self.grid_rows == 0 || self.grid_columns == 0 {
+            anyhow::bail!("grid_rows and grid_columns must be at least 1");
+        }
+        let cells = self
+            .grid_rows
+            .checked_mul(self.grid_columns)
+            .with_context(|| {
+                format!("A {}x{} grid is too large", self.grid_rows, self.grid_columns)
+            })? as usize;
+        if self.grid_keys.chars().count() < cells {
+            anyhow::bail!(
+                "grid_keys needs {} keys for a {}x{} grid, got {:?}",
+                cells,
+                self.grid_rows,
+                self.grid_columns,
+                self.grid_keys
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Watches the config file for changes
+///
+/// Editors usually save by writing a new file and renaming it over the old
+/// one, so the watch is on the containing directory and filtered by name.
+pub struct ConfigWatcher {
+    inotify: Inotify,
+    file_name: OsString,
+}
+
+impl ConfigWatcher {
+    /// Start watching the directory containing `path`
+    pub fn new(path: &Path) -> Result<Self> {
+        let dir = path.parent().context("Config path has no parent directory")?;
+        let file_name = path
+            .file_name()
+            .context("Config path has no file name")?
+            .to_os_string();
+
+        let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)
+            .context("Failed to initialize inotify")?;
+        inotify
+            .add_watch(
+                dir,
+                AddWatchFlags::IN_CLOSE_WRITE
+                    | AddWatchFlags::IN_MOVED_TO
+                    | AddWatchFlags::IN_CREATE
+                    | AddWatchFlags::IN_DELETE,
+            )
+            .with_context(|| format!("Failed to watch {:?}", dir))?;
+
+        Ok(Self { inotify, file_name })
+    }
+
+    /// Drain pending events and report whether the config file was touched
+    ///
+    /// A single save produces several events; they collapse into one reload.
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        loop {
+            match self.inotify.read_events() {
+                Ok(events) => {
+                    changed |= events
+                        .iter()
+                        .any(|e| e.name.as_deref() == Some(self.file_name.as_os_str()));
+                }
+                Err(Errno::EAGAIN) => break,
+                Err(e) => {
+                    log::warn!("Failed to read config watch events: {}", e);
+                    break;
+                }
+            }
+        }
+        changed
+    }
+}
+
+impl AsFd for ConfigWatcher {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inotify.as_fd()
+    }
 }
 
 /// Parse a color string like "#FF4500" or "#FF4500FF" into RGBA u32
@@ -379,4 +889,204 @@ mod tests {
         assert!(config.activation_key.modifiers.ctrl);
         assert!(config.activation_key.modifiers.super_key);
     }
+
+    #[test]
+    fn test_parse_copy_chord() {
+        let config = Config::default();
+        assert!(config.copy_chord.modifiers.ctrl);
+        assert_eq!(config.copy_chord.key, "c");
+
+        let config = Config::parse(r#"copy_chord = "C-S-c""#).unwrap();
+        assert!(config.copy_chord.modifiers.ctrl);
+        assert!(config.copy_chord.modifiers.shift);
+        assert_eq!(config.copy_chord.key, "c");
+    }
+
+    #[test]
+    fn test_parse_grid_config() {
+        let toml = r##"
+            grid_rows = 3
+            grid_columns = 3
+            grid_keys = "qweasdzxc"
+            grid_color = "#00000080"
+        "##;
+        let config = Config::parse(toml).unwrap();
+        assert_eq!(config.grid_rows, 3);
+        assert_eq!(config.grid_keys, "qweasdzxc");
+        assert_eq!(config.grid_color, 0x00000080);
+
+        // Too few keys for the grid
+        let toml = r#"
+            grid_rows = 3
+            grid_columns = 3
+        "#;
+        assert!(Config::parse(toml).is_err());
+
+        // A cell count that overflows is rejected rather than wrapping
+        let toml = r#"
+            grid_rows = 65536
+            grid_columns = 65536
+            grid_keys = "a"
+        "#;
+        assert!(Config::parse(toml).is_err());
+    }
+
+    #[test]
+    fn test_parse_keyboard_layout() {
+        let config = Config::default();
+        assert!(!config.physical_keys);
+        assert!(config.xkb_layout.is_empty());
+
+        let toml = r#"
+            xkb_layout = "us"
+            xkb_variant = "colemak"
+            physical_keys = true
+        "#;
+        let config = Config::parse(toml).unwrap();
+        assert_eq!(config.xkb_layout, "us");
+        assert_eq!(config.xkb_variant, "colemak");
+        assert!(config.physical_keys);
+    }
+
+    #[test]
+    fn test_parse_extra_buttons() {
+        let config = Config::default();
+        assert_eq!(config.buttons.button_for("m"), Some(MouseButton::Left));
+        assert_eq!(config.buttons.button_for(""), None);
+
+        let toml = r#"
+            buttons = "n b v"
+            button_back = "["
+            button_forward = "]"
+        "#;
+        let config = Config::parse(toml).unwrap();
+        assert_eq!(config.buttons.button_for("v"), Some(MouseButton::Right));
+        assert_eq!(config.buttons.button_for("["), Some(MouseButton::Back));
+        assert_eq!(config.buttons.button_for("]"), Some(MouseButton::Forward));
+        assert_eq!(config.buttons.button_for("m"), None);
+        assert!(config.is_normal_binding("["));
+        assert!(!config.is_normal_binding(""));
+    }
+
+    #[test]
+    fn test_normal_bindings() {
+        let config = Config::default();
+        assert!(config.is_normal_binding("h"));
+        assert!(config.is_normal_binding(&config.buttons.left));
+        assert!(config.is_normal_binding("w"));
+        assert!(!config.is_normal_binding("z"));
+        assert!(!config.passthrough);
+    }
+
+    #[test]
+    fn test_parse_repeat() {
+        assert_eq!(Config::default().repeat, vec![RepeatAction::Backspace]);
+
+        let config = Config::parse(r#"
+            repeat = "click backspace"
+            repeat_delay = 250
+            repeat_rate = 40
+        "#).unwrap();
+        assert_eq!(config.repeat, vec![RepeatAction::Click, RepeatAction::Backspace]);
+        assert_eq!(config.repeat_delay, 250);
+        assert_eq!(config.repeat_rate, 40);
+
+        let config = Config::parse(r#"repeat = """#).unwrap();
+        assert!(config.repeat.is_empty());
+
+        assert!(Config::parse(r#"repeat = "jump""#).is_err());
+    }
+
+    #[test]
+    fn test_parse_device_matchers() {
+        let toml = r#"
+            include_devices = ["/dev/input/by-id/usb-Kinesis-event-kbd", "29ea:0102"]
+            exclude_devices = ["Yubico YubiKey OTP+FIDO+CCID", "Dell KB216:Consumer"]
+        "#;
+        let config = Config::parse(toml).unwrap();
+        assert_eq!(
+            config.include_devices,
+            vec![
+                DeviceMatcher::Path(PathBuf::from("/dev/input/by-id/usb-Kinesis-event-kbd")),
+                DeviceMatcher::Id { vendor: 0x29ea, product: 0x0102 },
+            ]
+        );
+        assert_eq!(
+            config.exclude_devices,
+            vec![
+                DeviceMatcher::Name("Yubico YubiKey OTP+FIDO+CCID".to_string()),
+                DeviceMatcher::Name("Dell KB216:Consumer".to_string()),
+            ]
+        );
+
+        // Names that look like IDs
+        let toml = r#"
+            include_devices = ["ABC:123", "name:Cafe:Beef", "id:29EA:0102"]
+            exclude_devices = ["Cafe:Beef"]
+        "#;
+        let config = Config::parse(toml).unwrap();
+        assert_eq!(
+            config.include_devices,
+            vec![
+                DeviceMatcher::Name("ABC:123".to_string()),
+                DeviceMatcher::Name("Cafe:Beef".to_string()),
+                DeviceMatcher::Id { vendor: 0x29ea, product: 0x0102 },
+            ]
+        );
+        assert_eq!(config.exclude_devices, vec![DeviceMatcher::Id { vendor: 0xcafe, product: 0xbeef }]);
+
+        // A mistyped explicit ID is an error rather than a matcher that never matches
+        assert!(Config::parse(r#"exclude_devices = ["id:abcd:12345"]"#).is_err());
+        assert!(Config::parse(r#"include_devices = ["id:29ea"]"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_pointer_backend() {
+        assert_eq!(Config::default().pointer_backend, PointerBackendKind::Auto);
+        let config = Config::parse(r#"pointer_backend = "wlr""#).unwrap();
+        assert_eq!(config.pointer_backend, PointerBackendKind::Wlr);
+        let config = Config::parse(r#"pointer_backend = "kde""#).unwrap();
+        assert_eq!(config.pointer_backend, PointerBackendKind::Kde);
+        assert!(Config::parse(r#"pointer_backend = "x11""#).is_err());
+    }
+
+    #[test]
+    fn test_parse_badge_position() {
+        let config = Config::parse(r#"badge_position = "bottom-left""#).unwrap();
+        assert_eq!(config.badge_position, Some(BadgePosition::BottomLeft));
+        let config = Config::parse(r#"badge_position = "none""#).unwrap();
+        assert_eq!(config.badge_position, None);
+        assert!(Config::parse(r#"badge_position = "middle""#).is_err());
+    }
+
+    #[test]
+    fn test_parse_idle_timeout() {
+        assert_eq!(Config::default().idle_timeout, 0);
+        let config = Config::parse("idle_timeout = 30").unwrap();
+        assert_eq!(config.idle_timeout, 30);
+    }
+
+    #[test]
+    fn test_validate_hint_chars() {
+        assert!(Config::parse(r#"hint_chars = """#).is_err());
+    }
+
+    #[test]
+    fn test_config_watcher() {
+        let dir = std::env::temp_dir().join(format!("kwarpd-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("kwarpd.conf");
+
+        let watcher = ConfigWatcher::new(&path).unwrap();
+        assert!(!watcher.changed());
+
+        fs::write(dir.join("other.conf"), "").unwrap();
+        assert!(!watcher.changed());
+
+        fs::write(&path, "speed = 300").unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,185 @@
+//! KWarpd Daemon Module
+//!
+//! Detaching from the terminal, the PID file that keeps a second instance
+//! from grabbing the keyboard, and where logs go once stderr is gone
+
+use anyhow::{Context, Result};
+use nix::fcntl::{Flock, FlockArg};
+use nix::sys::stat::{umask, Mode};
+use nix::unistd::{chdir, dup2, fork, getpid, getppid, getuid, setsid, ForkResult, Pid};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+/// PID file name inside the runtime directory
+const PID_FILE_NAME: &str = "kwarpd.pid";
+
+/// Check whether systemd started us and is already supervising the process
+///
+/// A service manager wants a process that stays in the foreground and logs to
+/// stderr, which ends up in the journal. Variables like INVOCATION_ID are
+/// inherited by everything in a systemd-started session, terminals included,
+/// so only trust the parent: PID 1 for system services, or the user manager
+/// named by MANAGERPID for user services.
+pub fn under_systemd() -> bool {
+    let parent = getppid();
+    parent == Pid::from_raw(1)
+        || std::env::var("MANAGERPID")
+            .ok()
+            .and_then(|pid| pid.parse().ok())
+            .is_some_and(|pid| parent == Pid::from_raw(pid))
+}
+
+/// Get the PID file path
+///
+/// Prefers the runtime directory; `sudo kwarpd` usually has none, so fall back
+/// to /run for root and a per-user file in /tmp otherwise.
+fn pid_file_path() -> PathBuf {
+    if let Some(dir) = dirs::runtime_dir() {
+        return dir.join(PID_FILE_NAME);
+    }
+    let uid = getuid();
+    if uid.is_root() {
+        PathBuf::from("/run").join(PID_FILE_NAME)
+    } else {
+        PathBuf::from(format!("/tmp/kwarpd-{}.pid", uid))
+    }
+}
+
+/// Open the PID file without following symlinks
+///
+/// The /tmp fallback has a predictable name, so refuse anything that isn't a
+/// regular file of our own; otherwise another user could point it at a file
+/// of ours and have it truncated.
+fn open_pid_file(path: &Path) -> Result<File> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .mode(0o644)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)?;
+    let meta = file.metadata()?;
+    if !meta.file_type().is_file() {
+        anyhow::bail!("not a regular file");
+    }
+    if meta.uid() != getuid().as_raw() {
+        anyhow::bail!("owned by uid {}", meta.uid());
+    }
+    Ok(file)
+}
+
+/// Locked PID file, removed again on drop
+pub struct PidFile {
+    lock: Flock<File>,
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Lock the PID file, failing if another instance holds it
+    ///
+    /// The lock lives on the open file, so it survives forking and disappears
+    /// with the process even after a crash. If the file can't be opened, or
+    /// isn't ours, warn and run without the lock rather than refusing to start.
+    pub fn acquire() -> Result<Option<Self>> {
+        let path = pid_file_path();
+
+        let file = match open_pid_file(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                log::warn!(
+                    "Failed to open PID file {:?}, not guarding against a second instance: {:#}",
+                    path,
+                    e
+                );
+                return Ok(None);
+            }
+        };
+
+        let lock = match Flock::lock(file, FlockArg::LockExclusiveNonblock) {
+            Ok(lock) => lock,
+            Err((mut file, _)) => {
+                let mut pid = String::new();
+                let _ = file.read_to_string(&mut pid);
+                anyhow::bail!("kwarpd is already running (pid {})", pid.trim());
+            }
+        };
+
+        let mut pid_file = Self { lock, path };
+        pid_file.write_pid()?;
+        Ok(Some(pid_file))
+    }
+
+    /// Record the current process ID (call again after forking)
+    pub fn write_pid(&mut self) -> Result<()> {
+        self.lock.set_len(0)?;
+        self.lock.rewind()?;
+        writeln!(self.lock, "{}", getpid()).context("Failed to write PID file")?;
+        Ok(())
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Get the log file used while running detached
+pub fn log_path() -> Option<PathBuf> {
+    dirs::state_dir()
+        .or_else(dirs::runtime_dir)
+        .map(|p| p.join("kwarpd").join("kwarpd.log"))
+}
+
+/// Open the log file for appending, creating its directory if needed
+pub fn open_log() -> Result<File> {
+    let path = log_path().context("No state or runtime directory for the log file")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open log file {:?}", path))
+}
+
+/// Detach from the terminal with the classic double fork
+///
+/// Must run before any threads are spawned. Only the grandchild returns.
+pub fn daemonize() -> Result<()> {
+    // SAFETY: no other threads exist yet, so the child can keep running Rust code
+    match unsafe { fork() }.context("First fork failed")? {
+        ForkResult::Parent { .. } => std::process::exit(0),
+        ForkResult::Child => {}
+    }
+
+    // Leave the terminal's session so closing it doesn't send us SIGHUP
+    setsid().context("setsid failed")?;
+
+    // Fork again so we can never reacquire a controlling terminal
+    // SAFETY: as above
+    match unsafe { fork() }.context("Second fork failed")? {
+        ForkResult::Parent { .. } => std::process::exit(0),
+        ForkResult::Child => {}
+    }
+
+    chdir("/").context("Failed to change to /")?;
+    umask(Mode::from_bits_truncate(0o022));
+
+    // Detach stdio; logging goes to the log file from here on
+    let null = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")
+        .context("Failed to open /dev/null")?;
+    for fd in 0..=2 {
+        dup2(null.as_raw_fd(), fd).context("Failed to redirect stdio")?;
+    }
+
+    Ok(())
+}
@@ -4,14 +4,19 @@
 
 use anyhow::{Context, Result};
 use evdev::{Device, EventType, KeyCode};
+use nix::errno::Errno;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
 use std::fs;
-use std::os::unix::io::AsRawFd;
-use std::path::PathBuf;
+use std::os::fd::{AsFd, BorrowedFd};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
-use crate::config::{KeyBinding, Modifiers};
+use crate::config::{Config, DeviceMatcher, KeyBinding, Modifiers};
+use crate::keymap::KeyTranslator;
 
-/// Maps evdev key codes to readable key names
-fn key_to_name(key: KeyCode) -> Option<String> {
+/// Maps evdev key codes to readable key names (physical US-QWERTY positions)
+pub fn key_to_name(key: KeyCode) -> Option<String> {
     let name = match key {
         KeyCode::KEY_A => "a",
         KeyCode::KEY_B => "b",
@@ -86,6 +91,21 @@ fn key_to_name(key: KeyCode) -> Option<String> {
     Some(name.to_string())
 }
 
+/// Check whether a key is one of the modifiers tracked by `ModifierState`
+pub fn is_modifier(key: KeyCode) -> bool {
+    matches!(
+        key,
+        KeyCode::KEY_LEFTALT
+            | KeyCode::KEY_RIGHTALT
+            | KeyCode::KEY_LEFTCTRL
+            | KeyCode::KEY_RIGHTCTRL
+            | KeyCode::KEY_LEFTSHIFT
+            | KeyCode::KEY_RIGHTSHIFT
+            | KeyCode::KEY_LEFTMETA
+            | KeyCode::KEY_RIGHTMETA
+    )
+}
+
 /// Current modifier state
 #[derive(Debug, Clone, Default)]
 pub struct ModifierState {
@@ -158,38 +178,345 @@ impl ModifierState {
 /// A keyboard input event
 #[derive(Debug, Clone)]
 pub struct KeyEvent {
+    /// Physical key, independent of the layout
+    pub code: KeyCode,
+    /// Binding name, empty for keys that have none
     pub key: String,
     pub pressed: bool,
     pub modifiers: Modifiers,
 }
 
+/// Repeats a held key on kwarpd's own timer
+///
+/// Kernel autorepeat is ignored, so held-key behaviour stays under our
+/// control and follows the repeat settings in the config.
+#[derive(Debug, Default)]
+pub struct KeyRepeater {
+    held: Option<(KeyEvent, Instant)>,
+}
+
+impl KeyRepeater {
+    /// Start repeating `event` once `first` is reached
+    pub fn start(&mut self, event: KeyEvent, first: Instant) {
+        self.held = Some((event, first));
+    }
+
+    /// Stop repeating if `code` is the repeating key
+    pub fn release(&mut self, code: KeyCode) {
+        if self.held.as_ref().is_some_and(|(event, _)| event.code == code) {
+            self.held = None;
+        }
+    }
+
+    /// Stop repeating
+    pub fn cancel(&mut self) {
+        self.held = None;
+    }
+
+    /// Time of the next repeat, if a key is held
+    pub fn next_due(&self) -> Option<Instant> {
+        self.held.as_ref().map(|&(_, next)| next)
+    }
+
+    /// Return the held event if a repeat is due, scheduling the next one
+    pub fn due(&mut self, now: Instant, interval: Duration) -> Option<KeyEvent> {
+        let (event, next) = self.held.as_mut()?;
+        if now < *next {
+            return None;
+        }
+        // Don't burst to catch up after a stall
+        *next = (*next + interval).max(now);
+        Some(event.clone())
+    }
+}
+
+/// Directory scanned and watched for keyboard devices
+const INPUT_DIR: &str = "/dev/input";
+
+/// Stable symlinks to device nodes, shown by `kwarpd list-devices`
+const BY_ID_DIR: &str = "/dev/input/by-id";
+
+/// What the device filter looks at when deciding whether to grab a device
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    pub path: PathBuf,
+    pub name: String,
+    pub vendor: u16,
+    pub product: u16,
+    /// Reports at least one key or button
+    pub has_keys: bool,
+    /// Looks like a real keyboard (has KEY_A and KEY_ENTER)
+    pub is_keyboard: bool,
+}
+
+impl DeviceInfo {
+    /// Describe an opened device
+    pub fn of(path: &Path, device: &Device) -> Self {
+        let id = device.input_id();
+        let keys = device.supported_keys();
+        Self {
+            path: path.to_path_buf(),
+            name: device.name().unwrap_or("Unknown").to_string(),
+            vendor: id.vendor(),
+            product: id.product(),
+            has_keys: keys.is_some_and(|k| k.iter().next().is_some()),
+            is_keyboard: keys
+                .is_some_and(|k| k.contains(KeyCode::KEY_A) && k.contains(KeyCode::KEY_ENTER)),
+        }
+    }
+
+    /// Check whether a config matcher selects this device
+    fn matches(&self, matcher: &DeviceMatcher) -> bool {
+        match matcher {
+            DeviceMatcher::Name(name) => self.name == *name,
+            DeviceMatcher::Id { vendor, product } => {
+                self.vendor == *vendor && self.product == *product
+            }
+            DeviceMatcher::Path(path) => {
+                // by-id and by-path entries are symlinks to the event node
+                path == &self.path || fs::canonicalize(path).is_ok_and(|p| p == self.path)
+            }
+        }
+    }
+}
+
+/// Whether a device gets grabbed, and why
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verdict {
+    pub grab: bool,
+    pub reason: String,
+}
+
+impl Verdict {
+    fn grab(reason: impl Into<String>) -> Self {
+        Self { grab: true, reason: reason.into() }
+    }
+
+    fn skip(reason: impl Into<String>) -> Self {
+        Self { grab: false, reason: reason.into() }
+    }
+}
+
+/// Decides which devices kwarpd grabs, from the device lists in the config
+#[derive(Debug, Clone, Default)]
+pub struct DeviceFilter {
+    include: Vec<DeviceMatcher>,
+    exclude: Vec<DeviceMatcher>,
+}
+
+impl DeviceFilter {
+    /// Build the filter from `include_devices` / `exclude_devices`
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            include: config.include_devices.clone(),
+            exclude: config.exclude_devices.clone(),
+        }
+    }
+
+    /// Decide whether to grab a device
+    pub fn check(&self, info: &DeviceInfo) -> Verdict {
+        // Never listen to our own virtual devices
+        if info.name.starts_with("kwarpd ") {
+            return Verdict::skip("kwarpd's own virtual device");
+        }
+        if let Some(m) = self.exclude.iter().find(|m| info.matches(m)) {
+            return Verdict::skip(format!("excluded by {}", m));
+        }
+        if !self.include.is_empty() {
+            return match self.include.iter().find(|m| info.matches(m)) {
+                Some(_) if !info.has_keys => Verdict::skip("included, but reports no keys"),
+                Some(m) => Verdict::grab(format!("included by {}", m)),
+                None => Verdict::skip("not in include_devices"),
+            };
+        }
+        if info.is_keyboard {
+            Verdict::grab("keyboard (has KEY_A and KEY_ENTER)")
+        } else {
+            Verdict::skip("not a keyboard")
+        }
+    }
+}
+
+/// A device node and what kwarpd would do with it
+#[derive(Debug)]
+pub struct DeviceReport {
+    pub path: PathBuf,
+    /// `None` if the node could not be opened
+    pub info: Option<DeviceInfo>,
+    /// Symlinks under /dev/input/by-id pointing at the node
+    pub links: Vec<PathBuf>,
+    pub verdict: Verdict,
+}
+
+/// Describe every event node and whether it would be grabbed
+pub fn list_devices(config: &Config) -> Result<Vec<DeviceReport>> {
+    let filter = DeviceFilter::from_config(config);
+
+    let mut links: Vec<(PathBuf, PathBuf)> = Vec::new();
+    if let Ok(entries) = fs::read_dir(BY_ID_DIR) {
+        for entry in entries.flatten() {
+            if let Ok(target) = fs::canonicalize(entry.path()) {
+                links.push((target, entry.path()));
+            }
+        }
+    }
+
+    let mut reports = Vec::new();
+    for entry in fs::read_dir(INPUT_DIR).with_context(|| format!("Failed to read {}", INPUT_DIR))? {
+        let path = entry?.path();
+        if !path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with("event"))
+        {
+            continue;
+        }
+
+        let (info, verdict) = match Device::open(&path) {
+            Ok(device) => {
+                let info = DeviceInfo::of(&path, &device);
+                let verdict = filter.check(&info);
+                (Some(info), verdict)
+            }
+            Err(e) => (None, Verdict::skip(format!("cannot open: {}", e))),
+        };
+        let mut device_links: Vec<PathBuf> = links
+            .iter()
+            .filter(|(target, _)| *target == path)
+            .map(|(_, link)| link.clone())
+            .collect();
+        device_links.sort();
+
+        reports.push(DeviceReport {
+            path,
+            info,
+            links: device_links,
+            verdict,
+        });
+    }
+
+    // event2 before event10
+    reports.sort_by_key(|r| {
+        r.path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.trim_start_matches("event").parse::<u32>().ok())
+            .unwrap_or(u32::MAX)
+    });
+    Ok(reports)
+}
+
+/// Source of `Keyboard::id`
+static NEXT_KEYBOARD_ID: AtomicU64 = AtomicU64::new(0);
+
+/// An opened keyboard device and the node it was opened from
+struct Keyboard {
+    /// Unique for the life of the process, unlike event node paths
+    id: u64,
+    path: PathBuf,
+    device: Device,
+}
+
+/// Where the session gets key events from
+///
+/// `InputManager` reads real keyboards; tests substitute a scripted source.
+pub trait KeySource {
+    /// Grab all keyboards (exclusive access)
+    fn grab(&mut self) -> Result<()>;
+
+    /// Release grabbed keyboards
+    fn ungrab(&mut self) -> Result<()>;
+
+    /// Check if keyboards are currently grabbed
+    fn is_grabbed(&self) -> bool;
+
+    /// Return the key events that arrived since the last call, without blocking
+    fn poll_events(&mut self) -> Result<Vec<KeyEvent>>;
+
+    /// Check if current key + modifiers matches an activation binding
+    fn check_activation(&self, key: &str, binding: &KeyBinding) -> bool;
+
+    /// Find the key that produces a binding name in the active layout
+    fn key_for_name(&self, name: &str) -> Option<KeyCode>;
+
+    /// Replace the key translator after the keyboard layout settings changed
+    fn set_translator(&mut self, translator: KeyTranslator);
+
+    /// Apply new include/exclude lists, releasing and picking up devices to match
+    fn set_device_filter(&mut self, _filter: DeviceFilter) {}
+
+    /// Get the keyboards' file descriptors for the event loop, by keyboard ID
+    fn get_fds(&self) -> Vec<(u64, BorrowedFd<'_>)> {
+        Vec::new()
+    }
+
+    /// Pick up keyboards that were plugged in or removed since the last call
+    fn handle_hotplug(&mut self) {}
+}
+
 /// Input manager that handles keyboard device access
 pub struct InputManager {
-    devices: Vec<Device>,
+    devices: Vec<Keyboard>,
+    /// Watches /dev/input for keyboards being plugged in or removed
+    watcher: Option<Inotify>,
     grabbed: bool,
     modifier_state: ModifierState,
+    translator: KeyTranslator,
+    filter: DeviceFilter,
 }
 
 impl InputManager {
     /// Create a new input manager by finding all keyboard devices
-    pub fn new() -> Result<Self> {
-        let devices = Self::find_keyboards()?;
+    pub fn new(config: &Config) -> Result<Self> {
+        let translator = KeyTranslator::from_config(config).unwrap_or_else(|e| {
+            log::warn!("{:#}; falling back to physical key names", e);
+            KeyTranslator::physical()
+        });
+
+        // Start watching before the scan so no device slips through in between
+        let watcher = match Self::watch_input_dir() {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                log::warn!("Keyboard hotplug disabled: {:#}", e);
+                None
+            }
+        };
+
+        let filter = DeviceFilter::from_config(config);
+        let devices = Self::find_keyboards(&filter)?;
         if devices.is_empty() {
             anyhow::bail!("No keyboard devices found. Do you have permission to access /dev/input?");
         }
         log::info!("Found {} keyboard device(s)", devices.len());
         Ok(Self {
             devices,
+            watcher,
             grabbed: false,
             modifier_state: ModifierState::default(),
+            translator,
+            filter,
         })
     }
 
+    /// Set up an inotify watch for device nodes appearing and disappearing
+    fn watch_input_dir() -> Result<Inotify> {
+        let inotify = Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC)
+            .context("Failed to initialize inotify")?;
+        // udev fixes up permissions after creating the node, so also watch attribute changes
+        inotify
+            .add_watch(
+                INPUT_DIR,
+                AddWatchFlags::IN_CREATE | AddWatchFlags::IN_ATTRIB | AddWatchFlags::IN_DELETE,
+            )
+            .with_context(|| format!("Failed to watch {}", INPUT_DIR))?;
+        Ok(inotify)
+    }
+
     /// Find all keyboard input devices
-    fn find_keyboards() -> Result<Vec<Device>> {
+    fn find_keyboards(filter: &DeviceFilter) -> Result<Vec<Keyboard>> {
         let mut keyboards = Vec::new();
 
-        let input_dir = PathBuf::from("/dev/input");
+        let input_dir = PathBuf::from(INPUT_DIR);
         if !input_dir.exists() {
             anyhow::bail!("/dev/input not found");
         }
@@ -198,58 +525,117 @@ impl InputManager {
             let entry = entry?;
             let path = entry.path();
 
-            if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-                if !name.starts_with("event") {
-                    continue;
-                }
+            if let Some(keyboard) = Self::open_keyboard(&path, filter) {
+                keyboards.push(keyboard);
             }
+        }
 
-            match Device::open(&path) {
-                Ok(device) => {
-                    if let Some(keys) = device.supported_keys() {
-                        if keys.contains(KeyCode::KEY_A) && keys.contains(KeyCode::KEY_ENTER) {
-                            log::debug!("Found keyboard: {:?} - {:?}",
-                                path, device.name().unwrap_or("Unknown"));
-                            keyboards.push(device);
-                        }
-                    }
-                }
-                Err(e) => {
-                    log::trace!("Could not open {:?}: {}", path, e);
-                }
+        Ok(keyboards)
+    }
+
+    /// Open an event node if it is a keyboard we should listen to
+    fn open_keyboard(path: &Path, filter: &DeviceFilter) -> Option<Keyboard> {
+        let name = path.file_name().and_then(|n| n.to_str())?;
+        if !name.starts_with("event") {
+            return None;
+        }
+
+        let device = match Device::open(path) {
+            Ok(device) => device,
+            Err(e) => {
+                log::trace!("Could not open {:?}: {}", path, e);
+                return None;
             }
+        };
+
+        let info = DeviceInfo::of(path, &device);
+        let verdict = filter.check(&info);
+        if !verdict.grab {
+            log::trace!("Skipping {:?} - {:?}: {}", path, info.name, verdict.reason);
+            return None;
         }
 
-        Ok(keyboards)
+        log::debug!("Found keyboard: {:?} - {:?} ({})", path, info.name, verdict.reason);
+
+        // Every device is read whenever any of them is readable, so reads must not block
+        if let Err(e) = device.set_nonblocking(true) {
+            log::warn!("Failed to set {:?} non-blocking: {}", path, e);
+            return None;
+        }
+
+        Some(Keyboard {
+            id: NEXT_KEYBOARD_ID.fetch_add(1, Ordering::Relaxed),
+            path: path.to_path_buf(),
+            device,
+        })
     }
 
-    /// Get file descriptors for poll/select
-    pub fn get_fds(&self) -> Vec<i32> {
-        self.devices.iter().map(|d| d.as_raw_fd()).collect()
+    /// Start listening to a newly plugged-in keyboard
+    fn add_device(&mut self, path: &Path) {
+        let Some(mut keyboard) = Self::open_keyboard(path, &self.filter) else {
+            return;
+        };
+
+        // Keep the exclusive grab consistent across all keyboards
+        if self.grabbed
+            && let Err(e) = keyboard.device.grab()
+        {
+            log::warn!("Failed to grab new device {:?}: {}", keyboard.device.name(), e);
+        }
+
+        log::info!("Keyboard added: {:?} - {:?}", path, keyboard.device.name().unwrap_or("Unknown"));
+        self.devices.push(keyboard);
     }
 
-    /// Grab all keyboard devices (exclusive access)
-    pub fn grab(&mut self) -> Result<()> {
+    /// Forget a keyboard that has been unplugged
+    fn remove_device(&mut self, path: &Path) {
+        let count = self.devices.len();
+        self.devices.retain(|k| k.path != path);
+        if self.devices.len() != count {
+            log::info!("Keyboard removed: {:?}", path);
+        }
+    }
+
+    /// Get the file descriptor that becomes readable when keyboards come or go
+    pub fn hotplug_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.watcher.as_ref().map(|w| w.as_fd())
+    }
+
+    /// Get current modifier state
+    #[allow(dead_code)]
+    pub fn modifiers(&self) -> &ModifierState {
+        &self.modifier_state
+    }
+}
+
+impl KeySource for InputManager {
+    fn grab(&mut self) -> Result<()> {
         if self.grabbed {
             return Ok(());
         }
 
-        for device in &mut self.devices {
-            device.grab()
-                .with_context(|| format!("Failed to grab device: {:?}", device.name()))?;
+        for i in 0..self.devices.len() {
+            let device = &mut self.devices[i].device;
+            if let Err(e) = device.grab() {
+                let name = device.name().map(str::to_string);
+                // Don't leave a subset of the keyboards grabbed
+                for Keyboard { device, .. } in &mut self.devices[..i] {
+                    let _ = device.ungrab();
+                }
+                return Err(e).with_context(|| format!("Failed to grab device: {:?}", name));
+            }
         }
         self.grabbed = true;
         log::info!("Grabbed keyboard input");
         Ok(())
     }
 
-    /// Release grabbed devices
-    pub fn ungrab(&mut self) -> Result<()> {
+    fn ungrab(&mut self) -> Result<()> {
         if !self.grabbed {
             return Ok(());
         }
 
-        for device in &mut self.devices {
+        for Keyboard { device, .. } in &mut self.devices {
             if let Err(e) = device.ungrab() {
                 log::warn!("Failed to ungrab device {:?}: {}", device.name(), e);
             }
@@ -260,52 +646,127 @@ impl InputManager {
         Ok(())
     }
 
-    /// Check if devices are currently grabbed
-    pub fn is_grabbed(&self) -> bool {
+    fn is_grabbed(&self) -> bool {
         self.grabbed
     }
 
-    /// Poll for events from all devices (non-blocking if possible)
-    pub fn poll_events(&mut self) -> Result<Vec<KeyEvent>> {
+    /// Devices that report ENODEV have been unplugged and are dropped.
+    fn poll_events(&mut self) -> Result<Vec<KeyEvent>> {
         let mut events = Vec::new();
+        let mut unplugged = Vec::new();
 
-        for device in &mut self.devices {
-            if let Ok(ev_iter) = device.fetch_events() {
-                for ev in ev_iter {
-                    if ev.event_type() == EventType::KEY {
-                        let key = KeyCode::new(ev.code());
-                        let pressed = ev.value() == 1;
-                        let is_repeat = ev.value() == 2;
+        for Keyboard { path, device, .. } in &mut self.devices {
+            match device.fetch_events() {
+                Ok(ev_iter) => {
+                    for ev in ev_iter {
+                        if ev.event_type() == EventType::KEY {
+                            let key = KeyCode::new(ev.code());
+                            let pressed = ev.value() == 1;
+                            let is_repeat = ev.value() == 2;
 
-                        if is_repeat {
-                            continue;
-                        }
+                            if is_repeat {
+                                continue;
+                            }
 
-                        self.modifier_state.update(key, pressed);
+                            self.modifier_state.update(key, pressed);
 
-                        if let Some(key_name) = key_to_name(key) {
+                            let key_name = self.translator.name(key);
+                            self.translator.update(key, pressed);
+
+                            // Unnamed keys can't be bound but may still be passed through
                             events.push(KeyEvent {
-                                key: key_name,
+                                code: key,
+                                key: key_name.unwrap_or_default(),
                                 pressed,
                                 modifiers: self.modifier_state.to_modifiers(),
                             });
                         }
                     }
                 }
+                Err(e) if e.raw_os_error() == Some(libc::ENODEV) => {
+                    unplugged.push(path.clone());
+                }
+                // Every keyboard is polled, most with nothing to read
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                // One failing keyboard shouldn't stop the others
+                Err(e) => log::warn!("Failed to read events from {:?}: {}", path, e),
             }
         }
 
+        for path in unplugged {
+            self.remove_device(&path);
+        }
+
         Ok(events)
     }
 
-    /// Check if current key + modifiers matches an activation binding
-    pub fn check_activation(&self, key: &str, binding: &KeyBinding) -> bool {
+    fn check_activation(&self, key: &str, binding: &KeyBinding) -> bool {
         self.modifier_state.matches(binding, key)
     }
 
-    /// Get current modifier state
-    pub fn modifiers(&self) -> &ModifierState {
-        &self.modifier_state
+    fn key_for_name(&self, name: &str) -> Option<KeyCode> {
+        self.translator.key_for_name(name)
+    }
+
+    fn set_translator(&mut self, translator: KeyTranslator) {
+        self.translator = translator;
+    }
+
+    fn set_device_filter(&mut self, filter: DeviceFilter) {
+        self.filter = filter;
+
+        let filter = &self.filter;
+        self.devices.retain(|k| {
+            let keep = filter.check(&DeviceInfo::of(&k.path, &k.device)).grab;
+            if !keep {
+                log::info!("Keyboard released: {:?}", k.path);
+            }
+            keep
+        });
+
+        if let Ok(entries) = fs::read_dir(INPUT_DIR) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if !self.devices.iter().any(|k| k.path == path) {
+                    self.add_device(&path);
+                }
+            }
+        }
+        if self.devices.is_empty() {
+            log::warn!("No keyboards match the device filter");
+        }
+    }
+
+    fn get_fds(&self) -> Vec<(u64, BorrowedFd<'_>)> {
+        self.devices.iter().map(|k| (k.id, k.device.as_fd())).collect()
+    }
+
+    fn handle_hotplug(&mut self) {
+        let Some(watcher) = &self.watcher else {
+            return;
+        };
+
+        let events = match watcher.read_events() {
+            Ok(events) => events,
+            Err(Errno::EAGAIN) => return,
+            Err(e) => {
+                log::warn!("Failed to read inotify events: {}", e);
+                return;
+            }
+        };
+
+        for event in events {
+            let Some(name) = event.name else {
+                continue;
+            };
+            let path = Path::new(INPUT_DIR).join(name);
+
+            if event.mask.contains(AddWatchFlags::IN_DELETE) {
+                self.remove_device(&path);
+            } else if !self.devices.iter().any(|k| k.path == path) {
+                self.add_device(&path);
+            }
+        }
     }
 }
 
@@ -337,9 +798,11 @@ mod tests {
     #[test]
     fn test_key_binding_match() {
         let binding = KeyBinding::parse("A-M-c").unwrap();
-        let mut state = ModifierState::default();
-        state.left_alt = true;
-        state.left_meta = true;
+        let mut state = ModifierState {
+            left_alt: true,
+            left_meta: true,
+            ..Default::default()
+        };
 
         assert!(state.matches(&binding, "c"));
         assert!(!state.matches(&binding, "x"));
@@ -347,4 +810,69 @@ mod tests {
         state.left_ctrl = true;
         assert!(!state.matches(&binding, "c"));
     }
+
+    fn device(name: &str, is_keyboard: bool) -> DeviceInfo {
+        DeviceInfo {
+            path: PathBuf::from("/dev/input/event7"),
+            name: name.to_string(),
+            vendor: 0x1050,
+            product: 0x0407,
+            has_keys: true,
+            is_keyboard,
+        }
+    }
+
+    #[test]
+    fn test_device_filter() {
+        let filter = DeviceFilter::default();
+        assert!(filter.check(&device("AT Translated Set 2 keyboard", true)).grab);
+        assert!(!filter.check(&device("Power Button", false)).grab);
+        assert!(!filter.check(&device("kwarpd virtual keyboard", true)).grab);
+
+        let filter = DeviceFilter {
+            include: Vec::new(),
+            exclude: vec![DeviceMatcher::Id { vendor: 0x1050, product: 0x0407 }],
+        };
+        let verdict = filter.check(&device("Yubico YubiKey", true));
+        assert!(!verdict.grab);
+        assert_eq!(verdict.reason, "excluded by 1050:0407");
+
+        // Included devices are grabbed even if they don't look like keyboards
+        let filter = DeviceFilter {
+            include: vec![DeviceMatcher::Path(PathBuf::from("/dev/input/event7"))],
+            exclude: Vec::new(),
+        };
+        assert!(filter.check(&device("Macro Pad", false)).grab);
+        let mut other = device("AT Translated Set 2 keyboard", true);
+        other.path = PathBuf::from("/dev/input/event3");
+        assert!(!filter.check(&other).grab);
+    }
+
+    #[test]
+    fn test_key_repeater() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let interval = Duration::from_millis(40);
+        let event = KeyEvent {
+            code: KeyCode::KEY_BACKSPACE,
+            key: "backspace".to_string(),
+            pressed: true,
+            modifiers: Modifiers::default(),
+        };
+
+        let mut repeater = KeyRepeater::default();
+        repeater.start(event, at(400));
+        assert!(repeater.due(at(399), interval).is_none());
+        assert!(repeater.due(at(400), interval).is_some());
+        assert!(repeater.due(at(420), interval).is_none());
+        assert!(repeater.due(at(440), interval).is_some());
+        assert_eq!(repeater.next_due(), Some(at(480)));
+
+        // Releasing another key keeps it going, releasing this one stops it
+        repeater.release(KeyCode::KEY_A);
+        assert!(repeater.due(at(480), interval).is_some());
+        repeater.release(KeyCode::KEY_BACKSPACE);
+        assert!(repeater.due(at(520), interval).is_none());
+        assert_eq!(repeater.next_due(), None);
+    }
 }
@@ -0,0 +1,309 @@
+//! KWarpd Control Socket
+//!
+//! Line-based protocol over a Unix domain socket so scripts, KDE global
+//! shortcuts and `kwarpd ctl` can drive the running daemon
+
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use std::fs;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Socket file name inside the runtime directory
+const SOCKET_NAME: &str = "kwarpd.sock";
+
+/// How long `kwarpd ctl` waits for the daemon before giving up
+const IO_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Longest command line a client may send
+const MAX_LINE: usize = 256;
+
+/// Commands accepted on the control socket
+#[derive(Subcommand, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Enter normal mode
+    Normal,
+    /// Enter hint mode
+    Hint,
+    /// Enter grid mode
+    Grid,
+    /// Leave the active mode
+    Exit,
+    /// Print the current mode
+    Status,
+    /// Reload the configuration file
+    Reload,
+}
+
+impl Command {
+    /// Wire name of the command
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Command::Normal => "normal",
+            Command::Hint => "hint",
+            Command::Grid => "grid",
+            Command::Exit => "exit",
+            Command::Status => "status",
+            Command::Reload => "reload",
+        }
+    }
+
+    /// Parse a command from its wire name
+    pub fn parse(s: &str) -> Option<Self> {
+        let command = match s {
+            "normal" => Command::Normal,
+            "hint" => Command::Hint,
+            "grid" => Command::Grid,
+            "exit" => Command::Exit,
+            "status" => Command::Status,
+            "reload" => Command::Reload,
+            _ => return None,
+        };
+        Some(command)
+    }
+}
+
+/// Get the control socket path ($XDG_RUNTIME_DIR/kwarpd.sock)
+pub fn socket_path() -> Result<PathBuf> {
+    dirs::runtime_dir()
+        .map(|p| p.join(SOCKET_NAME))
+        .context("XDG_RUNTIME_DIR is not set")
+}
+
+/// Listening control socket, removed again on drop
+pub struct ControlServer {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl ControlServer {
+    /// Bind the control socket, replacing a stale socket file
+    pub fn bind() -> Result<Self> {
+        let path = socket_path()?;
+
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                anyhow::bail!("kwarpd is already running (socket {:?} is live)", path);
+            }
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove stale socket {:?}", path))?;
+        }
+
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("Failed to bind control socket {:?}", path))?;
+        listener
+            .set_nonblocking(true)
+            .context("Failed to set control socket non-blocking")?;
+
+        log::info!("Listening for commands on {:?}", path);
+        Ok(Self { listener, path })
+    }
+
+    /// Accept all pending clients
+    ///
+    /// Their commands are read from the returned connections as they arrive,
+    /// so a slow client can't hold up the main loop.
+    pub fn accept(&self) -> Vec<ControlClient> {
+        let mut clients = Vec::new();
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    log::warn!("Failed to accept control connection: {}", e);
+                    break;
+                }
+            };
+            match stream.set_nonblocking(true) {
+                Ok(()) => clients.push(ControlClient {
+                    stream,
+                    buffer: Vec::new(),
+                }),
+                Err(e) => log::warn!("Failed to set control connection non-blocking: {}", e),
+            }
+        }
+        clients
+    }
+}
+
+/// Accepted control connection waiting for its command
+pub struct ControlClient {
+    stream: UnixStream,
+    buffer: Vec<u8>,
+}
+
+impl ControlClient {
+    /// Read what the client has sent so far, and once the command line is
+    /// complete answer it with `handler`
+    ///
+    /// Returns whether the connection is finished with and can be dropped.
+    pub fn serve<F>(&mut self, handler: F) -> Result<bool>
+    where
+        F: FnOnce(Command) -> Result<String>,
+    {
+        let mut closed = false;
+        let mut chunk = [0u8; MAX_LINE];
+        while !self.buffer.contains(&b'\n') {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    closed = true;
+                    break;
+                }
+                Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e).context("Failed to read control command"),
+            }
+            if self.buffer.len() > MAX_LINE {
+                anyhow::bail!("Control command longer than {} bytes", MAX_LINE);
+            }
+        }
+
+        // A client may close its end instead of ending the line
+        let end = match self.buffer.iter().position(|&b| b == b'\n') {
+            Some(end) => end,
+            None if closed && !self.buffer.is_empty() => self.buffer.len(),
+            None => return Ok(closed),
+        };
+        let line = String::from_utf8_lossy(&self.buffer[..end]);
+        let line = line.trim();
+
+        let reply = match Command::parse(line) {
+            Some(command) => {
+                log::debug!("Control command: {}", line);
+                match handler(command) {
+                    Ok(text) => format!("ok {}", text),
+                    Err(e) => format!("error {:#}", e),
+                }
+            }
+            None => format!("error unknown command: {}", line),
+        };
+
+        // The reply is a single short line, well within the socket buffer
+        writeln!(self.stream, "{}", reply.trim_end()).context("Failed to send reply")?;
+        Ok(true)
+    }
+}
+
+impl AsFd for ControlClient {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.stream.as_fd()
+    }
+}
+
+impl AsFd for ControlServer {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.listener.as_fd()
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Send a command to the running daemon and return its reply text
+pub fn send_command(command: Command) -> Result<String> {
+    let path = socket_path()?;
+    let mut stream = UnixStream::connect(&path)
+        .with_context(|| format!("Failed to connect to {:?}. Is kwarpd running?", path))?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    writeln!(stream, "{}", command.as_str())?;
+
+    let mut line = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut line)
+        .context("No reply from kwarpd")?;
+    let line = line.trim_end();
+
+    match line.split_once(' ').unwrap_or((line, "")) {
+        ("ok", text) => Ok(text.to_string()),
+        ("error", message) => anyhow::bail!("{}", message),
+        _ => anyhow::bail!("Malformed reply from kwarpd: {:?}", line),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_round_trip() {
+        for command in [
+            Command::Normal,
+            Command::Hint,
+            Command::Grid,
+            Command::Exit,
+            Command::Status,
+            Command::Reload,
+        ] {
+            assert_eq!(Command::parse(command.as_str()), Some(command));
+        }
+        assert_eq!(Command::parse("bogus"), None);
+    }
+
+    fn connect() -> (ControlClient, UnixStream) {
+        let (server, client) = UnixStream::pair().unwrap();
+        server.set_nonblocking(true).unwrap();
+        let control = ControlClient {
+            stream: server,
+            buffer: Vec::new(),
+        };
+        (control, client)
+    }
+
+    fn read_reply(stream: &UnixStream) -> String {
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line).unwrap();
+        line
+    }
+
+    #[test]
+    fn test_serve_partial_command() {
+        let (mut control, mut client) = connect();
+
+        // Nothing to read yet, then half a command, waiting on the rest
+        assert!(!control.serve(|_| unreachable!()).unwrap());
+        client.write_all(b"hi").unwrap();
+        assert!(!control.serve(|_| unreachable!()).unwrap());
+
+        client.write_all(b"nt\n").unwrap();
+        let mut received = None;
+        let done = control
+            .serve(|command| {
+                received = Some(command);
+                Ok("hint".to_string())
+            })
+            .unwrap();
+        assert!(done);
+        assert_eq!(received, Some(Command::Hint));
+        assert_eq!(read_reply(&client), "ok hint\n");
+    }
+
+    #[test]
+    fn test_serve_unterminated_and_unknown() {
+        let (mut control, mut client) = connect();
+        client.write_all(b"bogus").unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+        assert!(control.serve(|_| unreachable!()).unwrap());
+        assert_eq!(read_reply(&client), "error unknown command: bogus\n");
+
+        // Closing without a command just ends the connection
+        let (mut control, client) = connect();
+        drop(client);
+        assert!(control.serve(|_| unreachable!()).unwrap());
+    }
+
+    #[test]
+    fn test_serve_overlong_command() {
+        let (mut control, mut client) = connect();
+        client.write_all(&[b'a'; MAX_LINE + 1]).unwrap();
+        assert!(control.serve(|_| unreachable!()).is_err());
+    }
+}
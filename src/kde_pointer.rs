@@ -0,0 +1,221 @@
+//! KWarpd KWin Fake Input
+//!
+//! Pointer backend using KWin's org_kde_kwin_fake_input Wayland protocol, for
+//! systems where /dev/uinput isn't writable. KWin only offers the interface to
+//! clients whose desktop file lists it under X-KDE-Wayland-Interfaces.
+
+use anyhow::{Context, Result};
+use wayland_client::{
+    Connection, Dispatch, EventQueue, QueueHandle,
+    globals::{GlobalListContents, registry_queue_init},
+    protocol::{wl_pointer, wl_registry},
+};
+
+use crate::config::MouseButton;
+use crate::output::{button_code, PointerBackend, AXIS_VALUE_PER_NOTCH, WHEEL_UNITS_PER_NOTCH};
+
+use self::protocol::org_kde_kwin_fake_input::OrgKdeKwinFakeInput;
+
+/// Client bindings generated from protocols/fake-input.xml
+pub mod protocol {
+    use wayland_client;
+
+    pub mod __interfaces {
+        use wayland_client::backend as wayland_backend;
+        wayland_scanner::generate_interfaces!("protocols/fake-input.xml");
+    }
+    use self::__interfaces::*;
+
+    wayland_scanner::generate_client_code!("protocols/fake-input.xml");
+}
+
+/// Dispatch target; fake input has no events
+struct State;
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(
+        _state: &mut Self,
+        _registry: &wl_registry::WlRegistry,
+        _event: wl_registry::Event,
+        _data: &GlobalListContents,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<OrgKdeKwinFakeInput, ()> for State {
+    fn event(
+        _state: &mut Self,
+        _fake_input: &OrgKdeKwinFakeInput,
+        _event: <OrgKdeKwinFakeInput as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+/// Pointer driven through KWin's fake input interface
+pub struct KdePointer {
+    event_queue: EventQueue<State>,
+    fake_input: OrgKdeKwinFakeInput,
+}
+
+impl KdePointer {
+    /// Connect to the compositor and authenticate for fake input
+    pub fn new() -> Result<Self> {
+        let conn = Connection::connect_to_env().context("Failed to connect to Wayland")?;
+        Self::with_connection(&conn)
+    }
+
+    fn with_connection(conn: &Connection) -> Result<Self> {
+        let (globals, mut event_queue) =
+            registry_queue_init::<State>(conn).context("Failed to initialize Wayland registry")?;
+        let qh = event_queue.handle();
+
+        // Version 3 added pointer_motion_absolute, which warps rely on
+        let fake_input: OrgKdeKwinFakeInput = globals
+            .bind(&qh, 3..=4, ())
+            .context(
+                "Compositor does not offer org_kde_kwin_fake_input version 3. KWin only \
+                 offers it to programs whose desktop file lists it under \
+                 X-KDE-Wayland-Interfaces (see assets/kwarpd.desktop)",
+            )?;
+        fake_input.authenticate(
+            "kwarpd".to_string(),
+            "Keyboard-driven pointer control".to_string(),
+        );
+
+        // Surface protocol errors now rather than on the first motion
+        event_queue
+            .roundtrip(&mut State)
+            .context("Failed to authenticate for fake input")?;
+
+        log::info!("Using KWin fake input");
+
+        Ok(Self {
+            event_queue,
+            fake_input,
+        })
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.event_queue
+            .flush()
+            .context("Failed to flush Wayland connection")
+    }
+}
+
+impl PointerBackend for KdePointer {
+    fn name(&self) -> &'static str {
+        "kde"
+    }
+
+    fn move_mouse(&mut self, dx: i32, dy: i32) -> Result<()> {
+        self.fake_input.pointer_motion(dx as f64, dy as f64);
+        self.flush()
+    }
+
+    /// Not needed: absolute motion already uses global coordinates
+    fn set_desktop_extent(&mut self, _x: i32, _y: i32, _width: u32, _height: u32) {}
+
+    fn warp_to(&mut self, x: i32, y: i32) -> Result<()> {
+        self.fake_input.pointer_motion_absolute(x as f64, y as f64);
+        self.flush()
+    }
+
+    fn button(&mut self, button: MouseButton, pressed: bool) -> Result<()> {
+        let state = if pressed {
+            wl_pointer::ButtonState::Pressed
+        } else {
+            wl_pointer::ButtonState::Released
+        };
+        self.fake_input
+            .button(button_code(button) as u32, state.into());
+        self.flush()
+    }
+
+    /// The protocol has no discrete steps, so this sends the smooth value only
+    fn scroll(&mut self, dx: i32, dy: i32) -> Result<()> {
+        let value = |units: i32| units as f64 / WHEEL_UNITS_PER_NOTCH as f64 * AXIS_VALUE_PER_NOTCH;
+        if dy != 0 {
+            self.fake_input
+                .axis(wl_pointer::Axis::VerticalScroll.into(), value(dy));
+        }
+        if dx != 0 {
+            self.fake_input
+                .axis(wl_pointer::Axis::HorizontalScroll.into(), value(dx));
+        }
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::protocol::__interfaces::ORG_KDE_KWIN_FAKE_INPUT_INTERFACE;
+    use super::*;
+    use crate::stub_compositor::{StubGlobal, StubServer};
+
+    /// Fake input at `version`
+    fn fake_input(version: u32) -> Option<StubGlobal> {
+        Some(StubGlobal {
+            interface: &ORG_KDE_KWIN_FAKE_INPUT_INTERFACE,
+            version,
+            timed: &[],
+        })
+    }
+
+    /// Wait until the stub has handled everything sent so far
+    fn sync(pointer: &mut KdePointer) {
+        crate::stub_compositor::sync(&mut pointer.event_queue, &mut State);
+    }
+
+    #[test]
+    fn test_authenticates_on_connect() {
+        let (server, conn) = StubServer::start(fake_input(4));
+        let _pointer = KdePointer::with_connection(&conn).unwrap();
+        assert_eq!(
+            server.take(),
+            vec!["authenticate kwarpd Keyboard-driven pointer control"]
+        );
+    }
+
+    #[test]
+    fn test_pointer_requests() {
+        let (server, conn) = StubServer::start(fake_input(4));
+        let mut pointer = KdePointer::with_connection(&conn).unwrap();
+        server.take();
+
+        pointer.move_mouse(5, -3).unwrap();
+        pointer.warp_to(1920, 540).unwrap();
+        pointer.button(MouseButton::Left, true).unwrap();
+        pointer.button(MouseButton::Left, false).unwrap();
+        pointer.scroll(-60, 240).unwrap();
+        sync(&mut pointer);
+
+        assert_eq!(
+            server.take(),
+            vec![
+                "pointer_motion 5 -3",
+                "pointer_motion_absolute 1920 540",
+                "button 272 1",
+                "button 272 0",
+                "axis 0 30",
+                "axis 1 -7.5",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_requires_absolute_motion() {
+        let (_server, conn) = StubServer::start(fake_input(2));
+        assert!(KdePointer::with_connection(&conn).is_err());
+    }
+
+    #[test]
+    fn test_missing_global() {
+        let (_server, conn) = StubServer::start(None);
+        assert!(KdePointer::with_connection(&conn).is_err());
+    }
+}
@@ -0,0 +1,400 @@
+//! KWarpd Keymap Module
+//!
+//! Translates evdev key codes into binding names, either through the active
+//! xkb layout or straight from the physical (US-QWERTY) scancode table
+
+use anyhow::{Context, Result};
+use evdev::KeyCode;
+use std::fs;
+use std::path::{Path, PathBuf};
+use xkbcommon::xkb;
+
+use crate::config::Config;
+use crate::input::key_to_name;
+
+/// Offset between evdev key codes and xkb key codes
+const EVDEV_OFFSET: u32 = 8;
+
+/// First button code; every keyboard key lies below it
+const BTN_MISC: u16 = 0x100;
+
+/// System keyboard configuration written by localectl
+const X11_KEYBOARD_CONF: &str = "/etc/X11/xorg.conf.d/00-keyboard.conf";
+
+/// System keyboard configuration on Debian-based systems
+const DEFAULT_KEYBOARD: &str = "/etc/default/keyboard";
+
+/// xkb rule names (RMLVO) selecting a keymap
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct LayoutNames {
+    rules: String,
+    model: String,
+    layout: String,
+    variant: String,
+    options: String,
+}
+
+impl LayoutNames {
+    /// Names set in the kwarpd config, if it picks a layout
+    fn from_config(config: &Config) -> Option<Self> {
+        (!config.xkb_layout.is_empty()).then(|| Self {
+            rules: config.xkb_rules.clone(),
+            model: config.xkb_model.clone(),
+            layout: config.xkb_layout.clone(),
+            variant: config.xkb_variant.clone(),
+            options: config.xkb_options.clone(),
+        })
+    }
+
+    /// Parse KDE's kxkbrc, which only applies when layouts are enabled there
+    fn from_kxkbrc(text: &str) -> Option<Self> {
+        let mut names = Self::default();
+        let mut enabled = false;
+        let mut in_layout = false;
+        for line in text.lines().map(str::trim) {
+            if line.starts_with('[') {
+                in_layout = line == "[Layout]";
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            if !in_layout {
+                continue;
+            }
+            let value = value.trim().to_string();
+            match key.trim() {
+                "Use" => enabled = value == "true",
+                "LayoutList" => names.layout = value,
+                "VariantList" => names.variant = value,
+                "Model" => names.model = value,
+                "Options" => names.options = value,
+                _ => {}
+            }
+        }
+        (enabled && !names.layout.is_empty()).then_some(names)
+    }
+
+    /// Parse the `Option "XkbLayout" "..."` lines of an X11 InputClass section
+    fn from_x11_conf(text: &str) -> Option<Self> {
+        let mut names = Self::default();
+        for line in text.lines() {
+            let words: Vec<&str> = line.split('"').map(str::trim).collect();
+            // Option "Name" "value" splits into ["Option", name, "", value, ""]
+            let [kind, name, _, value, ..] = words[..] else {
+                continue;
+            };
+            if kind != "Option" {
+                continue;
+            }
+            let value = value.to_string();
+            match name {
+                "XkbRules" => names.rules = value,
+                "XkbModel" => names.model = value,
+                "XkbLayout" => names.layout = value,
+                "XkbVariant" => names.variant = value,
+                "XkbOptions" => names.options = value,
+                _ => {}
+            }
+        }
+        (!names.layout.is_empty()).then_some(names)
+    }
+
+    /// Parse the `XKBLAYOUT="..."` assignments of /etc/default/keyboard
+    fn from_default_keyboard(text: &str) -> Option<Self> {
+        let mut names = Self::default();
+        for line in text.lines() {
+            let Some((key, value)) = line.trim().split_once('=') else {
+                continue;
+            };
+            let value = value.trim().trim_matches('"').to_string();
+            match key {
+                "XKBMODEL" => names.model = value,
+                "XKBLAYOUT" => names.layout = value,
+                "XKBVARIANT" => names.variant = value,
+                "XKBOPTIONS" => names.options = value,
+                _ => {}
+            }
+        }
+        (!names.layout.is_empty()).then_some(names)
+    }
+}
+
+/// Where the session's keyboard layout may be configured
+struct LayoutSources {
+    kxkbrc: Option<PathBuf>,
+    x11_conf: PathBuf,
+    default_keyboard: PathBuf,
+}
+
+impl LayoutSources {
+    fn system() -> Self {
+        Self {
+            kxkbrc: dirs::config_dir().map(|dir| dir.join("kxkbrc")),
+            x11_conf: PathBuf::from(X11_KEYBOARD_CONF),
+            default_keyboard: PathBuf::from(DEFAULT_KEYBOARD),
+        }
+    }
+
+    /// Pick the layout names: the kwarpd config, then KDE's layout settings,
+    /// then the system keyboard config, then the libxkbcommon defaults
+    fn resolve(&self, config: &Config) -> LayoutNames {
+        let read = |path: &Path| fs::read_to_string(path).ok();
+        LayoutNames::from_config(config)
+            .or_else(|| {
+                let path = self.kxkbrc.as_deref()?;
+                LayoutNames::from_kxkbrc(&read(path)?)
+            })
+            .or_else(|| LayoutNames::from_x11_conf(&read(&self.x11_conf)?))
+            .or_else(|| LayoutNames::from_default_keyboard(&read(&self.default_keyboard)?))
+            .unwrap_or_default()
+    }
+}
+
+/// Compiled xkb keymap plus the state tracking the active layout
+struct XkbKeymap {
+    keymap: xkb::Keymap,
+    state: xkb::State,
+}
+
+/// Translates key codes into the names used in key bindings
+pub struct KeyTranslator {
+    /// `None` when bindings refer to physical keys
+    xkb: Option<XkbKeymap>,
+}
+
+impl KeyTranslator {
+    /// Translator that uses the physical scancode table
+    pub fn physical() -> Self {
+        Self { xkb: None }
+    }
+
+    /// Build a translator from the keyboard layout settings in the config
+    ///
+    /// Without a layout in the config, the session layout is read from KDE's
+    /// kxkbrc and then the system keyboard config. Empty xkb names fall back
+    /// to the libxkbcommon defaults, which honour the `XKB_DEFAULT_*`
+    /// environment variables.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        if config.physical_keys {
+            return Ok(Self::physical());
+        }
+        Self::from_names(&LayoutSources::system().resolve(config))
+    }
+
+    fn from_names(names: &LayoutNames) -> Result<Self> {
+        log::debug!("Using xkb names {:?}", names);
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let options = (!names.options.is_empty()).then(|| names.options.clone());
+        let keymap = xkb::Keymap::new_from_names(
+            &context,
+            &names.rules,
+            &names.model,
+            &names.layout,
+            &names.variant,
+            options,
+            xkb::KEYMAP_COMPILE_NO_FLAGS,
+        )
+        .with_context(|| {
+            format!(
+                "Failed to compile xkb keymap (layout {:?}, variant {:?})",
+                names.layout, names.variant
+            )
+        })?;
+        let state = xkb::State::new(&keymap);
+
+        Ok(Self {
+            xkb: Some(XkbKeymap { keymap, state }),
+        })
+    }
+
+    /// Feed a key event into the xkb state so layout switches are tracked
+    pub fn update(&mut self, key: KeyCode, pressed: bool) {
+        if let Some(xkb) = &mut self.xkb {
+            let direction = if pressed {
+                xkb::KeyDirection::Down
+            } else {
+                xkb::KeyDirection::Up
+            };
+            xkb.state.update_key(xkb_keycode(key), direction);
+        }
+    }
+
+    /// Get the binding name for a key
+    ///
+    /// Names come from the unshifted keysym of the active layout, so `h`
+    /// stays `h` while Shift is held. Keys without a usable keysym fall back
+    /// to their physical name.
+    pub fn name(&self, key: KeyCode) -> Option<String> {
+        let Some(xkb) = &self.xkb else {
+            return key_to_name(key);
+        };
+
+        let keycode = xkb_keycode(key);
+        let layout = xkb.state.key_get_layout(keycode);
+        xkb.keymap
+            .key_get_syms_by_level(keycode, layout, 0)
+            .first()
+            .and_then(|&sym| keysym_to_name(sym))
+            .or_else(|| key_to_name(key))
+    }
+
+    /// Find the key that currently produces the given binding name
+    pub fn key_for_name(&self, name: &str) -> Option<KeyCode> {
+        (0..BTN_MISC)
+            .map(KeyCode::new)
+            .find(|&key| self.name(key).as_deref() == Some(name))
+    }
+}
+
+/// Convert an evdev key code to an xkb key code
+fn xkb_keycode(key: KeyCode) -> xkb::Keycode {
+    xkb::Keycode::new(key.code() as u32 + EVDEV_OFFSET)
+}
+
+/// Map a keysym to the name used in key bindings
+fn keysym_to_name(sym: xkb::Keysym) -> Option<String> {
+    let keysym_name = xkb::keysym_get_name(sym);
+    let name = match keysym_name.as_str() {
+        "Escape" => "esc",
+        "BackSpace" => "backspace",
+        "Tab" | "ISO_Left_Tab" => "tab",
+        "Return" | "KP_Enter" => "enter",
+        "space" => "space",
+        "Up" => "up",
+        "Down" => "down",
+        "Left" => "left",
+        "Right" => "right",
+        n if n.starts_with('F') && n[1..].parse::<u8>().is_ok() => {
+            return Some(n.to_lowercase());
+        }
+        _ => {
+            // Printable keys are named after the character they type
+            let ch = char::from_u32(xkb::keysym_to_utf32(sym))?;
+            if ch == '\0' || ch.is_control() {
+                return None;
+            }
+            return Some(ch.to_lowercase().collect());
+        }
+    };
+    Some(name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translator(layout: &str, variant: &str) -> KeyTranslator {
+        let config = Config {
+            xkb_layout: layout.to_string(),
+            xkb_variant: variant.to_string(),
+            ..Config::default()
+        };
+        KeyTranslator::from_config(&config).unwrap()
+    }
+
+    #[test]
+    fn test_physical_names() {
+        let t = KeyTranslator::physical();
+        assert_eq!(t.name(KeyCode::KEY_J).as_deref(), Some("j"));
+        assert_eq!(t.key_for_name("j"), Some(KeyCode::KEY_J));
+    }
+
+    #[test]
+    fn test_us_layout_names() {
+        let t = translator("us", "");
+        assert_eq!(t.name(KeyCode::KEY_H).as_deref(), Some("h"));
+        assert_eq!(t.name(KeyCode::KEY_COMMA).as_deref(), Some(","));
+        assert_eq!(t.name(KeyCode::KEY_ESC).as_deref(), Some("esc"));
+        assert_eq!(t.name(KeyCode::KEY_SPACE).as_deref(), Some("space"));
+        assert_eq!(t.name(KeyCode::KEY_F5).as_deref(), Some("f5"));
+    }
+
+    #[test]
+    fn test_dvorak_layout_names() {
+        let t = translator("us", "dvorak");
+        // Dvorak puts "h" where QWERTY has "j"
+        assert_eq!(t.name(KeyCode::KEY_J).as_deref(), Some("h"));
+        assert_eq!(t.key_for_name("h"), Some(KeyCode::KEY_J));
+    }
+
+    #[test]
+    fn test_german_layout_names() {
+        let t = translator("de", "");
+        assert_eq!(t.name(KeyCode::KEY_Y).as_deref(), Some("z"));
+        assert_eq!(t.name(KeyCode::KEY_Z).as_deref(), Some("y"));
+    }
+
+    #[test]
+    fn test_parse_kxkbrc() {
+        let names = LayoutNames::from_kxkbrc(
+            "[$Version]\nupdate_info=kxkb.upd:remove-empty-lists\n\n\
+             [Layout]\nLayoutList=us,de\nModel=pc104\nOptions=caps:escape\n\
+             Use=true\nVariantList=dvorak,\n",
+        )
+        .unwrap();
+        assert_eq!(names.layout, "us,de");
+        assert_eq!(names.variant, "dvorak,");
+        assert_eq!(names.model, "pc104");
+        assert_eq!(names.options, "caps:escape");
+
+        // Layouts configured but not enabled are ignored
+        assert!(LayoutNames::from_kxkbrc("[Layout]\nLayoutList=de\nUse=false\n").is_none());
+    }
+
+    #[test]
+    fn test_parse_system_keyboard() {
+        let names = LayoutNames::from_x11_conf(
+            "Section \"InputClass\"\n\
+             \tIdentifier \"system-keyboard\"\n\
+             \tMatchIsKeyboard \"on\"\n\
+             \tOption \"XkbLayout\" \"de\"\n\
+             \tOption \"XkbVariant\" \"nodeadkeys\"\n\
+             EndSection\n",
+        )
+        .unwrap();
+        assert_eq!(names.layout, "de");
+        assert_eq!(names.variant, "nodeadkeys");
+
+        let names = LayoutNames::from_default_keyboard(
+            "XKBMODEL=\"pc105\"\nXKBLAYOUT=\"fr\"\nXKBVARIANT=\"\"\nXKBOPTIONS=\"\"\n",
+        )
+        .unwrap();
+        assert_eq!(names.layout, "fr");
+        assert_eq!(names.model, "pc105");
+    }
+
+    #[test]
+    fn test_layout_fallback_order() {
+        let dir = std::env::temp_dir().join(format!("kwarpd-layout-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let sources = LayoutSources {
+            kxkbrc: Some(dir.join("kxkbrc")),
+            x11_conf: dir.join("00-keyboard.conf"),
+            default_keyboard: dir.join("keyboard"),
+        };
+        let layout = |config: &Config| sources.resolve(config).layout;
+
+        // Nothing configured anywhere leaves the xkb defaults
+        assert_eq!(sources.resolve(&Config::default()), LayoutNames::default());
+
+        fs::write(&sources.default_keyboard, "XKBLAYOUT=\"fr\"\n").unwrap();
+        assert_eq!(layout(&Config::default()), "fr");
+
+        fs::write(&sources.x11_conf, "Option \"XkbLayout\" \"de\"\n").unwrap();
+        assert_eq!(layout(&Config::default()), "de");
+
+        fs::write(sources.kxkbrc.as_ref().unwrap(), "[Layout]\nLayoutList=us\nUse=true\n")
+            .unwrap();
+        assert_eq!(layout(&Config::default()), "us");
+
+        // A layout in the kwarpd config wins over the session's
+        let config = Config {
+            xkb_layout: "gb".to_string(),
+            ..Config::default()
+        };
+        assert_eq!(layout(&config), "gb");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
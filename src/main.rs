@@ -2,23 +2,48 @@
 //!
 //! A modal keyboard-driven cursor manipulation tool inspired by warpd
 
+mod clock;
 mod config;
+mod daemon;
 mod input;
+mod ipc;
+mod kde_pointer;
+mod keymap;
 mod output;
 mod overlay;
+mod signals;
+#[cfg(test)]
+mod sim;
 mod state;
+#[cfg(test)]
+mod stub_compositor;
+mod wlr_pointer;
 
 use anyhow::{Context, Result};
-use clap::Parser;
+use calloop::generic::Generic;
+use calloop::timer::{TimeoutAction, Timer};
+use calloop::{EventLoop, Interest, LoopHandle, PostAction, RegistrationToken};
+use clap::{Parser, Subcommand};
+use evdev::KeyCode;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::thread;
 use std::time::{Duration, Instant};
 
-use crate::config::Config;
-use crate::input::InputManager;
-use crate::output::VirtualPointer;
-use crate::overlay::{calculate_hints, find_hint_exact, find_hint_by_prefix, HintPoint};
-use crate::state::{Action, AppState, Mode};
+use crate::clock::{Clock, SystemClock};
+use crate::config::{Config, ConfigWatcher, RepeatAction};
+use crate::daemon::PidFile;
+use crate::input::{is_modifier, DeviceFilter, InputManager, KeyEvent, KeyRepeater, KeySource};
+use crate::ipc::{Command, ControlClient, ControlServer};
+use crate::keymap::KeyTranslator;
+use crate::output::{Pointer, VirtualKeyboard, WHEEL_UNITS_PER_NOTCH};
+use crate::overlay::{
+    calculate_screen_hints, find_hint_exact, find_hint_by_prefix, GridView,
+    HintPoint, Overlay, ScreenRect,
+};
+use crate::signals::Signals;
+use crate::state::{Action, AppState, EmergencyEscape, GridRegion, Mode};
+use nix::sys::signal::Signal;
 
 /// Command-line arguments
 #[derive(Parser, Debug)]
@@ -28,59 +53,137 @@ struct Args {
     #[arg(short, long)]
     config: Option<String>,
 
-    /// Run in foreground mode (don't daemonize)
+    /// Run in foreground mode (don't daemonize, log to stderr)
     #[arg(short, long)]
     foreground: bool,
 
     /// Enable debug logging
     #[arg(short, long)]
     debug: bool,
+
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+}
+
+/// Subcommands; without one kwarpd runs the daemon
+#[derive(Subcommand, Debug)]
+enum CliCommand {
+    /// Send a command to the running daemon
+    Ctl {
+        #[command(subcommand)]
+        command: Command,
+    },
+    /// Show input devices and whether kwarpd would grab them
+    ListDevices,
+}
+
+/// Scroll velocity along one wheel axis
+#[derive(Debug, Default)]
+struct ScrollAxis {
+    /// Hundredths of a notch per second
+    velocity: f64,
+    /// Fraction of a high-resolution wheel unit not yet emitted
+    remainder: f64,
+}
+
+impl ScrollAxis {
+    /// Advance by `dt` towards direction `dir` and return the wheel units to emit
+    fn update(&mut self, dir: i32, dt: f64, config: &Config) -> i32 {
+        if dir != 0 {
+            // Start at the initial scroll speed rather than from rest
+            let initial = config.scroll_speed as f64;
+            if self.velocity * (dir as f64) < initial {
+                self.velocity = dir as f64 * initial;
+            }
+            let target_scroll = dir as f64 * config.scroll_max_speed as f64;
+            let scroll_accel = config.scroll_acceleration as f64 * dt;
+            self.velocity = move_towards(self.velocity, target_scroll, scroll_accel);
+        } else {
+            let scroll_decel = config.scroll_deceleration.unsigned_abs() as f64 * dt;
+            self.velocity = move_towards(self.velocity, 0.0, scroll_decel);
+        }
+
+        // Emit high-resolution wheel units and carry the fraction to the next frame
+        self.remainder += self.velocity * dt / 100.0 * WHEEL_UNITS_PER_NOTCH as f64;
+        let units = self.remainder.trunc();
+        self.remainder -= units;
+        units as i32
+    }
 }
 
+/// Longest time step simulated at once, in seconds
+///
+/// A frame that comes late after a stall is shortened to this rather than
+/// dropped, so the keys held during it still take effect.
+const MAX_FRAME_DT: f64 = 0.1;
+
 /// Physics state for smooth movement
 struct PhysicsState {
     velocity_x: f64,
     velocity_y: f64,
-    scroll_velocity: f64,
+    /// Fractions of a pixel not yet moved
+    remainder_x: f64,
+    remainder_y: f64,
+    scroll_x: ScrollAxis,
+    scroll_y: ScrollAxis,
     last_update: Instant,
 }
 
 impl PhysicsState {
-    fn new() -> Self {
+    fn new(now: Instant) -> Self {
         Self {
             velocity_x: 0.0,
             velocity_y: 0.0,
-            scroll_velocity: 0.0,
-            last_update: Instant::now(),
+            remainder_x: 0.0,
+            remainder_y: 0.0,
+            scroll_x: ScrollAxis::default(),
+            scroll_y: ScrollAxis::default(),
+            last_update: now,
         }
     }
 
-    fn reset(&mut self) {
+    fn reset(&mut self, now: Instant) {
         self.velocity_x = 0.0;
         self.velocity_y = 0.0;
-        self.scroll_velocity = 0.0;
-        self.last_update = Instant::now();
+        self.remainder_x = 0.0;
+        self.remainder_y = 0.0;
+        self.scroll_x = ScrollAxis::default();
+        self.scroll_y = ScrollAxis::default();
+        self.last_update = now;
+    }
+
+    /// Check whether the pointer and the wheels have come to rest
+    fn is_idle(&self) -> bool {
+        self.velocity_x == 0.0
+            && self.velocity_y == 0.0
+            && self.scroll_x.velocity == 0.0
+            && self.scroll_y.velocity == 0.0
     }
 
-    /// Update physics and return movement delta
-    fn update(&mut self, state: &AppState, config: &Config) -> (i32, i32, i32) {
-        let now = Instant::now();
-        let dt = now.duration_since(self.last_update).as_secs_f64();
+    /// Update physics up to `now` and return the movement delta and the
+    /// (horizontal, vertical) scroll in wheel units
+    fn update(
+        &mut self,
+        state: &AppState,
+        config: &Config,
+        now: Instant,
+    ) -> ((i32, i32), (i32, i32)) {
+        let dt = now.saturating_duration_since(self.last_update).as_secs_f64();
         self.last_update = now;
 
-        if dt <= 0.0 || dt > 0.1 {
-            // Skip if time delta is too large (probably first frame)
-            return (0, 0, 0);
+        if dt <= 0.0 {
+            return ((0, 0), (0, 0));
         }
+        let dt = dt.min(MAX_FRAME_DT);
 
         let (dir_x, dir_y) = state.movement.direction();
-        let scroll_dir = state.scroll.direction();
+        let (scroll_dx, scroll_dy) = state.scroll.direction();
 
         // Select acceleration based on modifier keys
         let accel = if state.movement.accelerating {
             config.accelerator_acceleration as f64
         } else if state.movement.decelerating {
-            0.0 // No acceleration when decelerating
+            f64::INFINITY // Switch to the slow speed at once for fine positioning
         } else {
             config.acceleration as f64
         };
@@ -116,22 +219,19 @@ impl PhysicsState {
         self.velocity_x = self.velocity_x.clamp(-max, max);
         self.velocity_y = self.velocity_y.clamp(-max, max);
 
-        // Calculate scroll velocity
-        if scroll_dir != 0 {
-            let target_scroll = scroll_dir as f64 * config.scroll_max_speed as f64;
-            let scroll_accel = config.scroll_acceleration as f64 * dt;
-            self.scroll_velocity = move_towards(self.scroll_velocity, target_scroll, scroll_accel);
-        } else {
-            let scroll_decel = config.scroll_deceleration.unsigned_abs() as f64 * dt;
-            self.scroll_velocity = move_towards(self.scroll_velocity, 0.0, scroll_decel);
-        }
+        // Calculate scroll on both axes
+        let scroll_x = self.scroll_x.update(scroll_dx, dt, config);
+        let scroll_y = self.scroll_y.update(scroll_dy, dt, config);
 
-        // Calculate movement deltas
-        let dx = (self.velocity_x * dt).round() as i32;
-        let dy = (self.velocity_y * dt).round() as i32;
-        let scroll = (self.scroll_velocity * dt / 100.0).round() as i32; // Scale scroll
+        // Move whole pixels and carry the fraction to the next frame
+        self.remainder_x += self.velocity_x * dt;
+        self.remainder_y += self.velocity_y * dt;
+        let dx = self.remainder_x.trunc();
+        let dy = self.remainder_y.trunc();
+        self.remainder_x -= dx;
+        self.remainder_y -= dy;
 
-        (dx, dy, scroll)
+        ((dx as i32, dy as i32), (scroll_x, scroll_y))
     }
 }
 
@@ -146,185 +246,1210 @@ fn move_towards(current: f64, target: f64, delta: f64) -> f64 {
     }
 }
 
-/// Main application loop
-fn run(config: Config) -> Result<()> {
-    let config = Arc::new(config);
+/// Physics tick used when no output reports its refresh rate
+const FALLBACK_FRAME: Duration = Duration::from_micros(16_667);
 
-    // Initialize input manager
-    let mut input = InputManager::new()
-        .context("Failed to initialize input manager")?;
+/// Screen assumed when no overlay surface could be configured
+const FALLBACK_SCREEN: ScreenRect = ScreenRect {
+    x: 0,
+    y: 0,
+    width: 1920,
+    height: 1080,
+};
 
-    // Initialize virtual pointer
-    let mut pointer = VirtualPointer::new()
-        .context("Failed to initialize virtual pointer")?;
+/// Everything the main loop operates on
+struct Session {
+    config: Arc<Config>,
+    /// Config file given on the command line, re-read on reload
+    config_path: Option<PathBuf>,
+    input: Box<dyn KeySource>,
+    pointer: Pointer,
+    /// Virtual keyboard for passthrough and copy_and_exit, if uinput is usable
+    keyboard: Option<VirtualKeyboard>,
+    overlay: Option<Overlay>,
+    state: AppState,
+    physics: PhysicsState,
+    hints: Vec<HintPoint>,
+    /// Cleared to leave the main loop and shut down cleanly
+    running: bool,
+    escape: EmergencyEscape,
+    repeater: KeyRepeater,
+    /// Keys currently held down on the virtual keyboard by passthrough
+    passed_through: HashSet<KeyCode>,
+    /// Time of the last key event, for the idle timeout
+    last_input: Instant,
+    /// Where kwarpd last warped the pointer, for the normal mode indicator
+    ///
+    /// Wayland doesn't let clients ask for the pointer position, so this is
+    /// only known right after a warp. Relative motion goes through pointer
+    /// acceleration and mixes with any physical mouse, so it forgets it.
+    cursor: Option<(i32, i32)>,
+    handle: LoopHandle<'static, Session>,
+    /// Event sources of the open keyboards, by keyboard ID
+    device_sources: HashMap<u64, RegistrationToken>,
+    /// Timer for the next physics frame, key repeat or watchdog, and when it fires
+    timer: Option<(RegistrationToken, Instant)>,
+    /// Whether physics frames are being scheduled
+    physics_ticking: bool,
+    /// Error that stopped the main loop
+    error: Option<anyhow::Error>,
+    clock: Box<dyn Clock>,
+}
 
-    // Application state
-    let mut state = AppState::new();
-    let mut physics = PhysicsState::new();
+impl Session {
+    /// Create an inactive session reading keys from `input` and moving `pointer`
+    ///
+    /// The virtual keyboard, overlay and config path are optional and start
+    /// out unset.
+    fn new(
+        config: Arc<Config>,
+        input: Box<dyn KeySource>,
+        pointer: Pointer,
+        handle: LoopHandle<'static, Session>,
+        clock: Box<dyn Clock>,
+    ) -> Self {
+        let now = clock.now();
+        let mut session = Self {
+            config,
+            config_path: None,
+            input,
+            pointer,
+            keyboard: None,
+            overlay: None,
+            state: AppState::new(),
+            physics: PhysicsState::new(now),
+            hints: Vec::new(),
+            running: true,
+            escape: EmergencyEscape::default(),
+            repeater: KeyRepeater::default(),
+            passed_through: HashSet::new(),
+            last_input: now,
+            cursor: None,
+            handle,
+            device_sources: HashMap::new(),
+            timer: None,
+            physics_ticking: false,
+            error: None,
+            clock,
+        };
+        session.pointer.set_desktop_extent(
+            FALLBACK_SCREEN.x,
+            FALLBACK_SCREEN.y,
+            FALLBACK_SCREEN.width,
+            FALLBACK_SCREEN.height,
+        );
+        session
+    }
 
-    // Hint state (used in hint mode)
-    let mut hints: Vec<HintPoint> = Vec::new();
-    let mut screen_width: u32 = 1920; // Default, would be queried from Wayland
-    let mut screen_height: u32 = 1080;
+    /// Enter normal mode and grab the keyboard
+    fn enter_normal(&mut self) -> Result<()> {
+        self.leave_mode()?;
+        self.state.enter_normal();
+        self.input.grab()?;
+        self.hide_overlay();
+        self.update_cursor();
+        Ok(())
+    }
 
-    log::info!("kwarpd started, waiting for activation key...");
-    log::info!("Normal mode: {:?}", config.activation_key);
-    log::info!("Hint mode: {:?}", config.hint_activation_key);
+    /// Show the cursor indicator where kwarpd put the pointer, in normal mode
+    /// and while that position is known
+    fn update_cursor(&mut self) {
+        let Some(overlay) = &mut self.overlay else {
+            return;
+        };
+        match self.cursor {
+            Some((x, y)) if self.state.mode == Mode::Normal => overlay.show_cursor(x, y),
+            _ => overlay.hide_cursor(),
+        }
+    }
+
+    /// Warp the pointer and remember where it went
+    fn warp_to(&mut self, x: i32, y: i32) -> Result<()> {
+        self.pointer.warp_to(x, y)?;
+        self.cursor = Some((x, y));
+        Ok(())
+    }
+
+    /// Enter hint mode, grab the keyboard and show the hint overlay
+    fn enter_hint(&mut self) -> Result<()> {
+        self.leave_mode()?;
+        self.state.enter_hint();
+        self.input.grab()?;
+        self.update_cursor();
+
+        let screens = self.show_overlay();
+        self.hints = calculate_screen_hints(
+            &screens,
+            &self.config.hint_chars,
+            self.config.hint_size,
+        );
+        if let Some(overlay) = &mut self.overlay {
+            overlay.set_hints(self.hints.clone());
+        }
+        Ok(())
+    }
+
+    /// Enter grid mode, grab the keyboard and show the grid over the whole desktop
+    fn enter_grid(&mut self) -> Result<()> {
+        self.leave_mode()?;
+        self.input.grab()?;
+
+        let screens = self.show_overlay();
+        let desktop = ScreenRect::bounding(&screens).unwrap_or(FALLBACK_SCREEN);
+        self.state.enter_grid(GridRegion {
+            x: desktop.x as f64,
+            y: desktop.y as f64,
+            width: desktop.width as f64,
+            height: desktop.height as f64,
+        });
+        self.update_cursor();
+        self.update_grid_overlay();
+        Ok(())
+    }
+
+    /// Redraw the grid for the current grid region
+    fn update_grid_overlay(&mut self) {
+        let (Some(overlay), Some(region)) = (&mut self.overlay, self.state.grid.region) else {
+            return;
+        };
+        overlay.set_grid(GridView {
+            region: ScreenRect {
+                x: region.x.round() as i32,
+                y: region.y.round() as i32,
+                width: region.width.round() as u32,
+                height: region.height.round() as u32,
+            },
+            rows: self.config.grid_rows,
+            columns: self.config.grid_columns,
+            keys: self.config.grid_keys.chars().collect(),
+        });
+    }
 
-    // Main loop
-    let frame_duration = Duration::from_millis(16); // ~60 FPS
+    /// Stop what the current mode has in progress, before any mode change
+    ///
+    /// Switching modes from a key or from the control socket goes through
+    /// here as well as exiting, so keys passed through in normal mode are
+    /// always released. A drag is kept so it can end in another mode.
+    fn leave_mode(&mut self) -> Result<()> {
+        self.repeater.cancel();
+        self.release_passthrough()?;
+        self.physics.reset(self.clock.now());
+        Ok(())
+    }
 
-    loop {
-        let frame_start = Instant::now();
+    /// Leave the active mode and release everything we hold
+    fn exit_mode(&mut self) -> Result<()> {
+        self.state.exit();
+        // The Esc release that ends a hold never arrives once ungrabbed
+        self.escape.reset();
+        self.leave_mode()?;
+        self.input.ungrab()?;
+        self.pointer.release_drag()?;
+        self.hide_overlay();
+        self.update_cursor();
+        Ok(())
+    }
 
-        // Poll for input events
-        let events = input.poll_events().unwrap_or_default();
+    /// Decide whether a key event goes to the focused window instead of kwarpd
+    ///
+    /// In normal mode with passthrough enabled, unbound keys and anything
+    /// typed with Ctrl, Alt or Super held are forwarded, except the exit key.
+    /// Releases follow their press so no key is left down.
+    fn should_pass_through(&self, event: &KeyEvent) -> bool {
+        if !event.pressed {
+            return self.passed_through.contains(&event.code);
+        }
+        if self.state.mode != Mode::Normal || !self.config.passthrough {
+            return false;
+        }
+        if self.keyboard.is_none() {
+            return false;
+        }
+        if event.key == self.config.exit {
+            return false;
+        }
+        let mods = &event.modifiers;
+        is_modifier(event.code)
+            || mods.ctrl
+            || mods.alt
+            || mods.super_key
+            || !self.config.is_normal_binding(&event.key)
+    }
 
+    /// Forward a key event through the virtual keyboard
+    fn pass_through(&mut self, event: &KeyEvent) -> Result<()> {
+        if event.pressed {
+            self.passed_through.insert(event.code);
+        } else {
+            self.passed_through.remove(&event.code);
+        }
+        match &mut self.keyboard {
+            Some(keyboard) => keyboard.key(event.code, event.pressed),
+            None => Ok(()),
+        }
+    }
+
+    /// Release every key still held down on the virtual keyboard
+    fn release_passthrough(&mut self) -> Result<()> {
+        let passed_through = std::mem::take(&mut self.passed_through);
+        if let Some(keyboard) = &mut self.keyboard {
+            for code in passed_through {
+                keyboard.key(code, false)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Release everything the session holds, whatever state it is in
+    ///
+    /// Unlike `exit_mode` every step runs even when an earlier one fails, so
+    /// a broken device can't leave the keyboard grabbed or a button held.
+    fn release_all(&mut self) {
+        self.repeater.cancel();
+        self.escape.reset();
+        if let Err(e) = self.release_passthrough() {
+            log::error!("Failed to release forwarded keys: {:#}", e);
+        }
+        if let Err(e) = self.pointer.release_drag() {
+            log::error!("Failed to release drag button: {:#}", e);
+        }
+        if let Err(e) = self.input.ungrab() {
+            log::error!("Failed to release keyboard grab: {:#}", e);
+        }
+        self.state.exit();
+        self.physics.reset(self.clock.now());
+        self.update_badge();
+        self.hide_overlay();
+        self.update_cursor();
+        if let Some(overlay) = &mut self.overlay {
+            let _ = overlay.dispatch_pending();
+        }
+    }
+
+    /// Stop the main loop, returning `error` from it
+    fn fail(&mut self, error: anyhow::Error) {
+        self.error.get_or_insert(error);
+        self.running = false;
+    }
+
+    /// Read and handle key events from every keyboard
+    fn read_input(&mut self) -> Result<()> {
+        let events = self.input.poll_events().context("Failed to read key events")?;
         for event in events {
-            match state.mode {
-                Mode::Inactive => {
-                    // Check for activation keys
-                    if event.pressed {
-                        if input.check_activation(&event.key, &config.activation_key) {
-                            log::info!("Entering Normal mode");
-                            state.enter_normal();
-                            input.grab()?;
-                            physics.reset();
-                        } else if input.check_activation(&event.key, &config.hint_activation_key) {
-                            log::info!("Entering Hint mode");
-                            state.enter_hint();
-                            input.grab()?;
-                            // Generate hints
-                            hints = calculate_hints(
-                                screen_width,
-                                screen_height,
-                                &config.hint_chars,
-                                config.hint_size,
-                            );
-                            // TODO: Show overlay via Wayland
-                        }
+            self.handle_key(event)?;
+        }
+        Ok(())
+    }
+
+    /// Register newly opened keyboards with the event loop and drop closed ones
+    fn sync_device_sources(&mut self) {
+        let fds = self.input.get_fds();
+
+        self.device_sources.retain(|id, token| {
+            let open = fds.iter().any(|(fd_id, _)| fd_id == id);
+            if !open {
+                self.handle.remove(*token);
+            }
+            open
+        });
+
+        for (id, fd) in fds {
+            if self.device_sources.contains_key(&id) {
+                continue;
+            }
+            let fd = match fd.try_clone_to_owned() {
+                Ok(fd) => fd,
+                Err(e) => {
+                    log::warn!("Failed to duplicate keyboard fd: {}", e);
+                    continue;
+                }
+            };
+            let source = Generic::new(fd, Interest::READ, calloop::Mode::Level);
+            let inserted = self.handle.insert_source(source, |_, _, session: &mut Session| {
+                if let Err(e) = session.read_input() {
+                    session.fail(e);
+                }
+                Ok(PostAction::Continue)
+            });
+            match inserted {
+                Ok(token) => {
+                    self.device_sources.insert(id, token);
+                }
+                Err(e) => log::warn!("Failed to register keyboard: {}", e.error),
+            }
+        }
+    }
+
+    /// Run the physics frame, key repeat and watchdogs that are due
+    fn tick(&mut self) -> Result<()> {
+        self.tick_repeat()?;
+        if self.physics_ticking {
+            self.update_physics()?;
+        }
+        self.check_watchdogs();
+        Ok(())
+    }
+
+    /// Time between physics frames, following the display refresh rate
+    fn frame_interval(&self) -> Duration {
+        self.overlay
+            .as_ref()
+            .and_then(Overlay::frame_interval)
+            .unwrap_or(FALLBACK_FRAME)
+    }
+
+    /// Find when `tick` next has work to do, if ever
+    fn next_deadline(&mut self) -> Option<Instant> {
+        // Physics only runs while a key is held or the pointer is still coasting
+        let ticking = self.state.mode == Mode::Normal
+            && (self.state.movement.is_moving()
+                || self.state.scroll.is_scrolling()
+                || !self.physics.is_idle());
+        if ticking && !self.physics_ticking {
+            // Start the first frame from now rather than from when we went idle
+            self.physics.reset(self.clock.now());
+        }
+        self.physics_ticking = ticking;
+
+        let mut deadlines = Vec::new();
+        if ticking {
+            deadlines.push(self.physics.last_update + self.frame_interval());
+        }
+        deadlines.extend(self.repeater.next_due());
+        if self.input.is_grabbed() {
+            deadlines.extend(self.escape.hold_deadline());
+        }
+        let timeout = Duration::from_secs(self.config.idle_timeout as u64);
+        if self.state.mode != Mode::Inactive && !timeout.is_zero() {
+            deadlines.push(self.last_input + timeout);
+        }
+        deadlines.into_iter().min()
+    }
+
+    /// Arm the timer for the next deadline, so the loop sleeps until then
+    fn reschedule(&mut self) {
+        let next = self.next_deadline();
+        if self.timer.map(|(_, at)| at) == next {
+            return;
+        }
+        if let Some((token, _)) = self.timer.take() {
+            self.handle.remove(token);
+        }
+        let Some(at) = next else {
+            return;
+        };
+
+        let inserted = self
+            .handle
+            .insert_source(Timer::from_deadline(at), |_, _, session: &mut Session| {
+                session.timer = None;
+                if let Err(e) = session.tick() {
+                    session.fail(e);
+                }
+                TimeoutAction::Drop
+            });
+        match inserted {
+            Ok(token) => self.timer = Some((token, at)),
+            Err(e) => log::error!("Failed to arm timer: {}", e.error),
+        }
+    }
+
+    /// Check the emergency Esc hold and the idle timeout
+    fn check_watchdogs(&mut self) {
+        let now = self.clock.now();
+
+        if self.input.is_grabbed() && self.escape.held(now) {
+            log::warn!("Esc held, forcing exit");
+            self.release_all();
+            return;
+        }
+
+        // Holding a movement or scroll key counts as activity
+        if self.state.movement.is_moving() || self.state.scroll.is_scrolling() {
+            self.last_input = now;
+        }
+        let timeout = Duration::from_secs(self.config.idle_timeout as u64);
+        if self.state.mode != Mode::Inactive
+            && !timeout.is_zero()
+            && now.duration_since(self.last_input) >= timeout
+        {
+            log::info!("No input for {}s, exiting mode", self.config.idle_timeout);
+            self.release_all();
+        }
+    }
+
+    /// Show the overlay and return the screens it covers
+    ///
+    /// Also maps the absolute pointer onto the desktop spanned by those screens.
+    fn show_overlay(&mut self) -> Vec<ScreenRect> {
+        let mut screens = Vec::new();
+        if let Some(overlay) = &mut self.overlay {
+            match overlay.show() {
+                Ok(()) => screens = overlay.screens(),
+                Err(e) => log::warn!("Failed to show overlay: {:#}", e),
+            }
+        }
+        if screens.is_empty() {
+            screens.push(FALLBACK_SCREEN);
+        }
+
+        if let Some(desktop) = ScreenRect::bounding(&screens) {
+            self.pointer
+                .set_desktop_extent(desktop.x, desktop.y, desktop.width, desktop.height);
+        }
+        screens
+    }
+
+    /// Show the current mode, drag state and speed modifier on the badge
+    fn update_badge(&mut self) {
+        if let Some(overlay) = &mut self.overlay {
+            overlay.set_badge(self.state.badge_text().as_deref());
+        }
+    }
+
+    /// Hide the overlay and forget the current hints
+    fn hide_overlay(&mut self) {
+        if let Some(overlay) = &mut self.overlay {
+            overlay.hide();
+        }
+        self.hints.clear();
+    }
+
+    /// Handle a single key event
+    fn handle_key(&mut self, event: KeyEvent) -> Result<()> {
+        let now = self.clock.now();
+        self.last_input = now;
+
+        // Checked before any binding so it works however the config looks
+        if event.code == KeyCode::KEY_ESC
+            && self.input.is_grabbed()
+            && self.escape.key(event.pressed, now)
+        {
+            log::warn!("Esc pressed repeatedly, forcing exit");
+            self.release_all();
+            return Ok(());
+        }
+
+        // Any other key press, or releasing the held key, stops a repeat
+        if !event.pressed {
+            self.repeater.release(event.code);
+        } else if !is_modifier(event.code) {
+            self.repeater.cancel();
+        }
+
+        if self.should_pass_through(&event) {
+            return self.pass_through(&event);
+        }
+
+        let mode = self.state.mode;
+        let held = (event.pressed && self.is_repeatable(&event.key)).then(|| event.clone());
+        self.dispatch_key(event)?;
+        if let Some(event) = held
+            && self.state.mode == mode
+        {
+            let delay = Duration::from_millis(self.config.repeat_delay as u64);
+            self.repeater.start(event, now + delay);
+        }
+        Ok(())
+    }
+
+    /// Check whether holding `key` repeats its action in the current mode
+    fn is_repeatable(&self, key: &str) -> bool {
+        let config = &self.config;
+        if config.repeat_rate == 0 {
+            return false;
+        }
+        let action = match self.state.mode {
+            Mode::Normal if config.buttons.button_for(key).is_some() => RepeatAction::Click,
+            Mode::Hint | Mode::Grid if key == "backspace" => RepeatAction::Backspace,
+            _ => return false,
+        };
+        config.repeat.contains(&action)
+    }
+
+    /// Re-dispatch the held key if its next repeat is due
+    fn tick_repeat(&mut self) -> Result<()> {
+        if self.config.repeat_rate == 0 {
+            self.repeater.cancel();
+            return Ok(());
+        }
+
+        let now = self.clock.now();
+        let interval = Duration::from_secs(1) / self.config.repeat_rate;
+        if let Some(event) = self.repeater.due(now, interval) {
+            self.last_input = now;
+            let mode = self.state.mode;
+            self.dispatch_key(event)?;
+            if self.state.mode != mode {
+                self.repeater.cancel();
+            }
+        }
+        Ok(())
+    }
+
+    /// Act on a key event according to the current mode
+    fn dispatch_key(&mut self, event: KeyEvent) -> Result<()> {
+        match self.state.mode {
+            Mode::Inactive => {
+                // Check for activation keys
+                if event.pressed {
+                    if self.input.check_activation(&event.key, &self.config.activation_key) {
+                        log::info!("Entering Normal mode");
+                        self.enter_normal()?;
+                    } else if self.input.check_activation(&event.key, &self.config.hint_activation_key) {
+                        log::info!("Entering Hint mode");
+                        self.enter_hint()?;
+                    } else if self.input.check_activation(&event.key, &self.config.grid_activation_key) {
+                        log::info!("Entering Grid mode");
+                        self.enter_grid()?;
                     }
                 }
+            }
 
-                Mode::Normal | Mode::Hint => {
-                    let action = state.process_key(&event.key, event.pressed, &config);
-
-                    match action {
-                        Action::Exit => {
-                            log::info!("Exiting mode");
-                            state.exit();
-                            input.ungrab()?;
-                            physics.reset();
-                            pointer.release_drag()?;
-                            hints.clear();
-                        }
+            Mode::Normal | Mode::Hint | Mode::Grid => {
+                let action = self.state.process_key(&event.key, event.pressed, &self.config);
 
-                        Action::EnterHint => {
-                            log::info!("Switching to Hint mode");
-                            state.enter_hint();
-                            hints = calculate_hints(
-                                screen_width,
-                                screen_height,
-                                &config.hint_chars,
-                                config.hint_size,
-                            );
-                        }
+                match action {
+                    Action::Exit => {
+                        log::info!("Exiting mode");
+                        self.exit_mode()?;
+                    }
 
-                        Action::EnterNormal => {
-                            log::info!("Switching to Normal mode");
-                            state.enter_normal();
-                            hints.clear();
-                        }
+                    Action::EnterHint => {
+                        log::info!("Switching to Hint mode");
+                        self.enter_hint()?;
+                    }
 
-                        Action::Click(button) => {
-                            log::debug!("Click button {}", button);
-                            pointer.click(button)?;
-                        }
+                    Action::EnterGrid => {
+                        log::info!("Switching to Grid mode");
+                        self.enter_grid()?;
+                    }
 
-                        Action::ToggleDrag => {
-                            let dragging = pointer.toggle_drag()?;
-                            log::info!("Drag mode: {}", if dragging { "on" } else { "off" });
-                        }
+                    Action::EnterNormal => {
+                        log::info!("Switching to Normal mode");
+                        self.enter_normal()?;
+                    }
+
+                    Action::Click(button) => {
+                        log::debug!("Click button {:?}", button);
+                        self.pointer.click(button)?;
+                    }
 
-                        Action::CopyAndExit => {
-                            // Send Ctrl+C via uinput would be complex,
-                            // for now just exit
-                            log::info!("Copy and exit (Ctrl+C not implemented)");
-                            state.exit();
-                            input.ungrab()?;
-                            pointer.release_drag()?;
+                    Action::ToggleDrag => {
+                        let dragging = self.pointer.toggle_drag()?;
+                        log::info!("Drag mode: {}", if dragging { "on" } else { "off" });
+                    }
+
+                    Action::CopyAndExit => {
+                        log::info!("Copy and exit");
+                        // Exiting releases a held drag button and the keyboard
+                        // grab, so the chord copies the finished selection
+                        self.exit_mode()?;
+                        let chord = &self.config.copy_chord;
+                        // A chord the layout can't type is a config mistake, not
+                        // a reason to stop the daemon
+                        match (self.input.key_for_name(&chord.key), &mut self.keyboard) {
+                            (None, _) => {
+                                log::warn!("Unknown key in copy_chord: {}, not copying", chord.key)
+                            }
+                            (Some(key), Some(keyboard)) => {
+                                keyboard.send_chord(key, &chord.modifiers)?
+                            }
+                            (Some(_), None) => {
+                                log::warn!("No virtual keyboard, cannot send copy chord")
+                            }
                         }
+                    }
+
+                    Action::HintChar(ch) => {
+                        log::debug!("Hint char: {}", ch);
+                        let buffer = &self.state.hint_buffer;
 
-                        Action::HintChar(ch) => {
-                            log::debug!("Hint char: {}", ch);
-                            let buffer = &state.hint_buffer;
-
-                            // Check for exact match
-                            if let Some(hint) = find_hint_exact(&hints, buffer) {
-                                log::info!("Hint matched: {} -> ({}, {})", buffer, hint.x, hint.y);
-                                // TODO: Warp cursor to hint position (requires absolute positioning)
-                                // For now, print the position
-                                state.exit();
-                                input.ungrab()?;
-                                hints.clear();
-                            } else {
-                                // Check if any hints match the prefix
-                                let matches = find_hint_by_prefix(&hints, buffer);
-                                if matches.is_empty() {
-                                    log::debug!("No hints match prefix: {}", buffer);
-                                    state.hint_buffer.clear();
-                                }
+                        // Check for exact match
+                        if let Some(hint) = find_hint_exact(&self.hints, buffer) {
+                            log::info!("Hint matched: {} -> ({}, {})", buffer, hint.x, hint.y);
+                            let (x, y) = (hint.x, hint.y);
+                            self.warp_to(x, y)?;
+                            self.exit_mode()?;
+                        } else {
+                            // Check if any hints match the prefix
+                            let matches = find_hint_by_prefix(&self.hints, buffer);
+                            if matches.is_empty() {
+                                log::debug!("No hints match prefix: {}", buffer);
+                                self.state.hint_buffer.clear();
                             }
                         }
+                    }
 
-                        _ => {}
+                    Action::GridSelect => {
+                        self.update_grid_overlay();
+                    }
+
+                    Action::GridConfirm => {
+                        if let Some(region) = self.state.grid.region {
+                            let (x, y) = region.center();
+                            log::info!("Grid selected ({}, {})", x, y);
+                            self.warp_to(x, y)?;
+                        }
+                        self.exit_mode()?;
                     }
+
+                    _ => {}
+                }
+
+                // Keep the overlay in sync with typed hint characters
+                if self.state.mode == Mode::Hint
+                    && let Some(overlay) = &mut self.overlay
+                {
+                    overlay.set_highlight(&self.state.hint_buffer);
                 }
             }
         }
+        self.update_badge();
+        Ok(())
+    }
 
-        // Update physics and move pointer (only in normal mode with movement)
-        if state.mode == Mode::Normal {
-            let (dx, dy, scroll) = physics.update(&state, &config);
+    /// Read a control connection from its own event source until it is answered
+    fn add_control_client(&mut self, client: ControlClient) {
+        let source = Generic::new(client, Interest::READ, calloop::Mode::Level);
+        let result = self.handle.insert_source(source, |_, client, session: &mut Session| {
+            // SAFETY: the stream is only read and written, never dropped or replaced
+            let client = unsafe { client.get_mut() };
+            match client.serve(|command| session.handle_command(command)) {
+                Ok(false) => Ok(PostAction::Continue),
+                Ok(true) => Ok(PostAction::Remove),
+                Err(e) => {
+                    log::warn!("Control connection failed: {:#}", e);
+                    Ok(PostAction::Remove)
+                }
+            }
+        });
+        if let Err(e) = result {
+            log::warn!("Failed to register control connection: {}", e.error);
+        }
+    }
 
-            if dx != 0 || dy != 0 {
-                pointer.move_mouse(dx, dy)?;
+    /// Handle a command from the control socket and return the resulting mode
+    fn handle_command(&mut self, command: Command) -> Result<String> {
+        self.last_input = self.clock.now();
+        match command {
+            Command::Normal => {
+                log::info!("Entering Normal mode (control socket)");
+                self.enter_normal()?;
             }
+            Command::Hint => {
+                log::info!("Entering Hint mode (control socket)");
+                self.enter_hint()?;
+            }
+            Command::Grid => {
+                log::info!("Entering Grid mode (control socket)");
+                self.enter_grid()?;
+            }
+            Command::Exit => {
+                if self.state.mode != Mode::Inactive {
+                    log::info!("Exiting mode (control socket)");
+                    self.exit_mode()?;
+                }
+            }
+            Command::Status => {}
+            Command::Reload => self.reload_config()?,
+        }
+        self.update_badge();
+        Ok(self.state.mode.name().to_string())
+    }
+
+    /// Re-read the config file and swap it in
+    ///
+    /// Everything is parsed and validated before anything is replaced, so on
+    /// error the current config stays in place.
+    fn reload_config(&mut self) -> Result<()> {
+        let config = Arc::new(load_config(self.config_path.as_ref())?);
+        let translator = KeyTranslator::from_config(&config)?;
+
+        self.input.set_translator(translator);
+        self.input.set_device_filter(DeviceFilter::from_config(&config));
+        if let Some(overlay) = &mut self.overlay {
+            overlay.set_config(config.clone());
+        }
+        self.config = config;
+
+        log::info!("Configuration reloaded");
+        Ok(())
+    }
+
+    /// Reload the config, logging instead of failing when the new one is invalid
+    fn try_reload(&mut self) {
+        if let Err(e) = self.reload_config() {
+            log::error!("Keeping previous config: {:#}", e);
+        }
+    }
 
-            if scroll != 0 {
-                pointer.scroll(scroll)?;
+    /// Update physics and move pointer (only in normal mode with movement)
+    fn update_physics(&mut self) -> Result<()> {
+        if self.state.mode != Mode::Normal {
+            return Ok(());
+        }
+
+        let now = self.clock.now();
+        let ((dx, dy), (scroll_x, scroll_y)) = self.physics.update(&self.state, &self.config, now);
+
+        if dx != 0 || dy != 0 {
+            self.pointer.move_mouse(dx, dy)?;
+            // Where the compositor takes relative motion is unknown
+            if self.cursor.take().is_some() {
+                self.update_cursor();
             }
         }
 
-        // Frame rate limiting
-        let elapsed = frame_start.elapsed();
-        if elapsed < frame_duration {
-            thread::sleep(frame_duration - elapsed);
+        if scroll_x != 0 || scroll_y != 0 {
+            self.pointer.scroll(scroll_x, scroll_y)?;
+        }
+        Ok(())
+    }
+}
+
+/// Make a config path from the command line absolute
+///
+/// The daemon changes to / when it detaches, after which a relative path
+/// would be reloaded and watched from the wrong place.
+fn config_path_arg(path: &str) -> Result<PathBuf> {
+    std::path::absolute(path).with_context(|| format!("Invalid config path {:?}", path))
+}
+
+/// Load the config file given on the command line, or the default one
+fn load_config(path: Option<&PathBuf>) -> Result<Config> {
+    match path {
+        Some(path) => Config::load_from_file(path),
+        None => Config::load(),
+    }
+}
+
+/// Print every input device with the reason it would or wouldn't be grabbed
+fn print_devices(config: &Config) -> Result<()> {
+    for report in input::list_devices(config)? {
+        let action = if report.verdict.grab { "grab" } else { "skip" };
+        match &report.info {
+            Some(info) => println!(
+                "{}  {}  {:04x}:{:04x}  {:?}",
+                report.path.display(),
+                action,
+                info.vendor,
+                info.product,
+                info.name
+            ),
+            None => println!("{}  {}", report.path.display(), action),
+        }
+        println!("    {}", report.verdict.reason);
+        for link in &report.links {
+            println!("    {}", link.display());
+        }
+    }
+    Ok(())
+}
+
+/// Main application loop
+fn run(config: Config, config_path: Option<PathBuf>) -> Result<()> {
+    let config = Arc::new(config);
+
+    // Initialize input manager
+    let input = InputManager::new(&config)
+        .context("Failed to initialize input manager")?;
+
+    // Initialize virtual pointer
+    let pointer = output::open_pointer(config.pointer_backend)
+        .context("Failed to initialize virtual pointer")?;
+
+    // Initialize virtual keyboard (for passthrough and copy_and_exit)
+    let keyboard = match VirtualKeyboard::new() {
+        Ok(keyboard) => Some(keyboard),
+        Err(e) => {
+            log::warn!(
+                "Virtual keyboard unavailable, passthrough and copy_and_exit disabled: {:#}",
+                e
+            );
+            None
+        }
+    };
+
+    // Connect to the compositor for the hint overlay
+    let overlay = match Overlay::connect(config.clone()) {
+        Ok(overlay) => Some(overlay),
+        Err(e) => {
+            log::warn!("Overlay unavailable, hints will not be drawn: {:#}", e);
+            None
+        }
+    };
+
+    let mut event_loop: EventLoop<'static, Session> =
+        EventLoop::try_new().context("Failed to create event loop")?;
+
+    // Pick up keyboards plugged in or removed; their sources follow in the main loop
+    if let Some(fd) = input.hotplug_fd() {
+        let fd = fd.try_clone_to_owned().context("Failed to duplicate inotify fd")?;
+        event_loop
+            .handle()
+            .insert_source(
+                Generic::new(fd, Interest::READ, calloop::Mode::Level),
+                |_, _, session: &mut Session| {
+                    session.input.handle_hotplug();
+                    Ok(PostAction::Continue)
+                },
+            )
+            .map_err(|e| e.error)
+            .context("Failed to register hotplug watcher")?;
+    }
+
+    // Dispatch Wayland events whenever the connection becomes readable
+    if let Some(ref overlay) = overlay {
+        let source = Generic::new(overlay.poll_fd()?, Interest::READ, calloop::Mode::Level);
+        event_loop
+            .handle()
+            .insert_source(source, |_, _, session: &mut Session| {
+                if let Some(overlay) = &mut session.overlay
+                    && let Err(e) = overlay.read_events()
+                {
+                    log::warn!("Lost Wayland connection: {:#}", e);
+                    session.overlay = None;
+                    return Ok(PostAction::Remove);
+                }
+                Ok(PostAction::Continue)
+            })
+            .map_err(|e| e.error)
+            .context("Failed to register Wayland event source")?;
+    }
+
+    // Accept commands from `kwarpd ctl` and scripts
+    match ControlServer::bind() {
+        Ok(server) => {
+            let source = Generic::new(server, Interest::READ, calloop::Mode::Level);
+            event_loop
+                .handle()
+                .insert_source(source, |_, server, session: &mut Session| {
+                    for client in server.accept() {
+                        session.add_control_client(client);
+                    }
+                    Ok(PostAction::Continue)
+                })
+                .map_err(|e| e.error)
+                .context("Failed to register control socket")?;
+        }
+        Err(e) if ipc::socket_path().is_ok_and(|p| p.exists()) => return Err(e),
+        Err(e) => log::warn!("Control socket disabled: {:#}", e),
+    }
+
+    // Reload when the config file changes or on SIGHUP
+    let watch_path = config_path.clone().or_else(Config::default_path);
+    match watch_path.as_deref().map(ConfigWatcher::new) {
+        Some(Ok(watcher)) => {
+            let source = Generic::new(watcher, Interest::READ, calloop::Mode::Level);
+            event_loop
+                .handle()
+                .insert_source(source, |_, watcher, session: &mut Session| {
+                    if watcher.changed() {
+                        log::info!("Config file changed, reloading");
+                        session.try_reload();
+                    }
+                    Ok(PostAction::Continue)
+                })
+                .map_err(|e| e.error)
+                .context("Failed to register config watcher")?;
         }
+        Some(Err(e)) => log::info!("Config file watching disabled: {:#}", e),
+        None => {}
     }
+
+    let signals = Signals::new(&[Signal::SIGHUP, Signal::SIGINT, Signal::SIGTERM])
+        .context("Failed to set up signal handling")?;
+    event_loop
+        .handle()
+        .insert_source(
+            Generic::new(signals, Interest::READ, calloop::Mode::Level),
+            |_, signals, session: &mut Session| {
+                for signal in signals.pending() {
+                    match signal {
+                        Signal::SIGHUP => {
+                            log::info!("Received SIGHUP, reloading config");
+                            session.try_reload();
+                        }
+                        Signal::SIGINT | Signal::SIGTERM => {
+                            log::info!("Received {}, shutting down", signal);
+                            session.running = false;
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(PostAction::Continue)
+            },
+        )
+        .map_err(|e| e.error)
+        .context("Failed to register signal source")?;
+
+    let mut session = Session::new(
+        config.clone(),
+        Box::new(input),
+        pointer,
+        event_loop.handle(),
+        Box::new(SystemClock),
+    );
+    session.config_path = config_path;
+    session.keyboard = keyboard;
+    session.overlay = overlay;
+
+    log::info!("kwarpd started, waiting for activation key...");
+    log::info!("Normal mode: {:?}", config.activation_key);
+    log::info!("Hint mode: {:?}", config.hint_activation_key);
+    log::info!("Grid mode: {:?}", config.grid_activation_key);
+
+    // Clean up whether the loop stopped on a signal or on an error, before the
+    // session is dropped and the virtual devices are destroyed
+    let result = main_loop(&mut event_loop, &mut session);
+    session.release_all();
+    log::info!("kwarpd stopped");
+    result
+}
+
+/// Process input and timers until a signal or an error stops the session
+///
+/// Sleeps until a keyboard, the Wayland connection, the control socket, a
+/// signal or a timer needs attention. Timers are only armed while there is
+/// something to do, so an inactive kwarpd doesn't wake up at all.
+fn main_loop(event_loop: &mut EventLoop<'static, Session>, session: &mut Session) -> Result<()> {
+    while session.running {
+        // Keep keyboard sources in step with hotplug, reloads and unplugged devices
+        session.sync_device_sources();
+        session.reschedule();
+
+        // Flush overlay requests made while handling events
+        if let Some(overlay) = &mut session.overlay
+            && let Err(e) = overlay.dispatch_pending()
+        {
+            log::warn!("Lost Wayland connection: {:#}", e);
+            session.overlay = None;
+        }
+
+        event_loop
+            .dispatch(None, session)
+            .context("Event loop dispatch failed")?;
+    }
+    session.error.take().map_or(Ok(()), Err)
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
+    // Under systemd the service manager does the detaching and the journal
+    // collects stderr, so stay in the foreground there
+    let detach = args.command.is_none() && !args.foreground && !daemon::under_systemd();
+
     // Initialize logging
-    env_logger::Builder::from_default_env()
-        .filter_level(if args.debug {
-            log::LevelFilter::Debug
-        } else {
-            log::LevelFilter::Info
-        })
-        .init();
+    let mut logger = env_logger::Builder::from_default_env();
+    logger.filter_level(if args.debug {
+        log::LevelFilter::Debug
+    } else {
+        log::LevelFilter::Info
+    });
+    if detach {
+        let log_file = daemon::open_log()?;
+        logger.target(env_logger::Target::Pipe(Box::new(log_file)));
+    }
+    logger.init();
+
+    if let Some(CliCommand::Ctl { command }) = args.command {
+        let reply = ipc::send_command(command)?;
+        if !reply.is_empty() {
+            println!("{}", reply);
+        }
+        return Ok(());
+    }
 
     // Load configuration
-    let config = if let Some(ref path) = args.config {
-        Config::load_from_file(&std::path::PathBuf::from(path))?
-    } else {
-        Config::load()?
-    };
+    let config_path = args.config.as_deref().map(config_path_arg).transpose()?;
+    let config = load_config(config_path.as_ref())?;
 
     log::debug!("Configuration loaded: {:?}", config);
 
+    if let Some(CliCommand::ListDevices) = args.command {
+        return print_devices(&config);
+    }
+
+    // Refuse to start a second instance before detaching, so the error still
+    // reaches the terminal
+    let mut pid_file = PidFile::acquire()?;
+    if detach {
+        if let Some(path) = daemon::log_path() {
+            eprintln!("kwarpd: running in the background, logging to {}", path.display());
+        }
+        daemon::daemonize()?;
+        if let Some(pid_file) = &mut pid_file {
+            pid_file.write_pid()?;
+        }
+    }
+
     // Run the main loop
-    run(config)
+    let result = run(config, config_path);
+    if let Err(ref e) = result {
+        // stderr is gone once detached, so make sure the log has it
+        log::error!("{:#}", e);
+    }
+    drop(pid_file);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_config_path() {
+        let path = config_path_arg("kwarpd.conf").unwrap();
+        assert_eq!(path, std::env::current_dir().unwrap().join("kwarpd.conf"));
+        // The config watcher needs a directory to watch
+        assert!(path.parent().is_some_and(|dir| dir.is_absolute()));
+
+        let path = config_path_arg("/etc/kwarpd.conf").unwrap();
+        assert_eq!(path, PathBuf::from("/etc/kwarpd.conf"));
+    }
+
+    /// Length of the physics frames in these tests
+    const FRAME: Duration = Duration::from_millis(10);
+
+    /// Run `frames` physics frames and return the total pointer movement
+    fn run_frames(
+        physics: &mut PhysicsState,
+        state: &AppState,
+        config: &Config,
+        frames: u32,
+    ) -> (i32, i32) {
+        let mut total = (0, 0);
+        for _ in 0..frames {
+            let now = physics.last_update + FRAME;
+            let ((dx, dy), _) = physics.update(state, config, now);
+            total = (total.0 + dx, total.1 + dy);
+        }
+        total
+    }
+
+    fn moving_right() -> AppState {
+        let mut state = AppState::new();
+        state.movement.right = true;
+        state
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-6, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn test_acceleration_curve() {
+        let config = Config::default();
+        let mut physics = PhysicsState::new(Instant::now());
+        let state = moving_right();
+
+        // Speeds up linearly at `acceleration`...
+        run_frames(&mut physics, &state, &config, 10);
+        assert_close(physics.velocity_x, 70.0);
+        run_frames(&mut physics, &state, &config, 10);
+        assert_close(physics.velocity_x, 140.0);
+        assert_eq!(physics.velocity_y, 0.0);
+
+        // ...until it reaches `speed`
+        run_frames(&mut physics, &state, &config, 100);
+        assert_close(physics.velocity_x, config.speed as f64);
+    }
+
+    #[test]
+    fn test_accelerator() {
+        let config = Config::default();
+        let mut physics = PhysicsState::new(Instant::now());
+        let mut state = moving_right();
+        state.movement.accelerating = true;
+
+        run_frames(&mut physics, &state, &config, 50);
+        assert_close(physics.velocity_x, 1450.0);
+        run_frames(&mut physics, &state, &config, 100);
+        assert_close(physics.velocity_x, config.max_speed as f64);
+    }
+
+    #[test]
+    fn test_decelerator() {
+        let config = Config::default();
+        let mut physics = PhysicsState::new(Instant::now());
+        let mut state = moving_right();
+        run_frames(&mut physics, &state, &config, 100);
+
+        // Drops to the decelerator speed at once, from any speed
+        state.movement.decelerating = true;
+        run_frames(&mut physics, &state, &config, 1);
+        assert_close(physics.velocity_x, config.decelerator_speed as f64);
+
+        // It also moves from rest
+        let mut physics = PhysicsState::new(Instant::now());
+        let (dx, dy) = run_frames(&mut physics, &state, &config, 100);
+        assert_eq!((dx, dy), (config.decelerator_speed as i32, 0));
+    }
+
+    #[test]
+    fn test_max_speed_clamp() {
+        let config = Config {
+            speed: 5000,
+            acceleration: 100_000,
+            ..Config::default()
+        };
+        let mut physics = PhysicsState::new(Instant::now());
+        let mut state = moving_right();
+        state.movement.down = true;
+
+        run_frames(&mut physics, &state, &config, 100);
+        assert_close(physics.velocity_x, config.max_speed as f64);
+        assert_close(physics.velocity_y, config.max_speed as f64);
+    }
+
+    #[test]
+    fn test_subpixel_motion() {
+        // 0.3 pixels per frame, which rounding each frame would lose entirely
+        let config = Config {
+            decelerator_speed: 30,
+            ..Config::default()
+        };
+        let mut physics = PhysicsState::new(Instant::now());
+        let mut state = moving_right();
+        state.movement.decelerating = true;
+
+        let (dx, _) = run_frames(&mut physics, &state, &config, 100);
+        assert!((29..=30).contains(&dx), "moved {} pixels", dx);
+
+        state.movement.right = false;
+        state.movement.left = true;
+        let (dx, _) = run_frames(&mut physics, &state, &config, 100);
+        assert!((-30..=-29).contains(&dx), "moved {} pixels", dx);
+    }
+
+    #[test]
+    fn test_late_frame_is_shortened() {
+        let config = Config::default();
+        let start = Instant::now();
+        let mut physics = PhysicsState::new(start);
+        let state = moving_right();
+
+        // A frame arriving after a stall counts as MAX_FRAME_DT, not nothing
+        let ((dx, _), _) = physics.update(&state, &config, start + Duration::from_secs(2));
+        assert_close(physics.velocity_x, config.acceleration as f64 * MAX_FRAME_DT);
+        assert_eq!(dx, 7);
+
+        // A clock that hasn't moved yields no motion
+        let now = physics.last_update;
+        assert_eq!(physics.update(&state, &config, now), ((0, 0), (0, 0)));
+    }
+
+    #[test]
+    fn test_coast_to_stop() {
+        let config = Config::default();
+        let mut physics = PhysicsState::new(Instant::now());
+        run_frames(&mut physics, &moving_right(), &config, 100);
+
+        // Slows at twice the acceleration once the keys are released
+        let (dx, _) = run_frames(&mut physics, &AppState::new(), &config, 20);
+        assert!(physics.is_idle());
+        assert!((16..=18).contains(&dx), "coasted {} pixels", dx);
+    }
 }
@@ -1,15 +1,21 @@
 //! KWarpd Output Manager
 //!
-//! Handles virtual mouse device creation and control via uinput
+//! Pointer backends behind a common trait, and the virtual mouse and keyboard
+//! devices created via uinput
 
 use anyhow::{Context, Result};
 use bytemuck::{Pod, Zeroable};
+use evdev::KeyCode;
 use std::fs::{File, OpenOptions};
 use std::io::Write;
 use std::os::unix::io::AsRawFd;
 use std::thread;
 use std::time::Duration;
 
+use crate::config::{Modifiers, MouseButton, PointerBackendKind};
+use crate::kde_pointer::KdePointer;
+use crate::wlr_pointer::WlrPointer;
+
 // uinput constants
 const UINPUT_PATH: &str = "/dev/uinput";
 
@@ -17,6 +23,7 @@ const UINPUT_PATH: &str = "/dev/uinput";
 const EV_SYN: u16 = 0x00;
 const EV_KEY: u16 = 0x01;
 const EV_REL: u16 = 0x02;
+const EV_ABS: u16 = 0x03;
 
 // Sync events
 const SYN_REPORT: u16 = 0;
@@ -26,16 +33,51 @@ const REL_X: u16 = 0x00;
 const REL_Y: u16 = 0x01;
 const REL_WHEEL: u16 = 0x08;
 const REL_HWHEEL: u16 = 0x06;
+const REL_WHEEL_HI_RES: u16 = 0x0b;
+const REL_HWHEEL_HI_RES: u16 = 0x0c;
+
+/// High-resolution wheel units per notch
+pub const WHEEL_UNITS_PER_NOTCH: i32 = 120;
+
+/// wl_pointer axis value of one wheel notch, as libinput reports it
+pub(crate) const AXIS_VALUE_PER_NOTCH: f64 = 15.0;
+
+// Absolute axis codes
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+
+/// Largest value on the absolute device's axes
+///
+/// The range is fixed so the device never has to be recreated; the compositor
+/// maps it onto the whole desktop and warps are scaled to match.
+const ABS_MAX: i32 = 0xffff;
 
-// Button codes
+// Button codes (keyboard keys are everything below BTN_MISC)
+const BTN_MISC: u16 = 0x100;
 const BTN_LEFT: u16 = 0x110;
 const BTN_RIGHT: u16 = 0x111;
 const BTN_MIDDLE: u16 = 0x112;
+const BTN_SIDE: u16 = 0x113;
+const BTN_EXTRA: u16 = 0x114;
+const BTN_FORWARD: u16 = 0x115;
+const BTN_BACK: u16 = 0x116;
+
+/// Every button the virtual pointer advertises
+const POINTER_BUTTONS: [u16; 7] = [
+    BTN_LEFT,
+    BTN_RIGHT,
+    BTN_MIDDLE,
+    BTN_SIDE,
+    BTN_EXTRA,
+    BTN_FORWARD,
+    BTN_BACK,
+];
 
 // uinput ioctl commands
 const UI_SET_EVBIT: u64 = 0x40045564;
 const UI_SET_KEYBIT: u64 = 0x40045565;
 const UI_SET_RELBIT: u64 = 0x40045566;
+const UI_SET_ABSBIT: u64 = 0x40045567;
 const UI_DEV_CREATE: u64 = 0x5501;
 const UI_DEV_DESTROY: u64 = 0x5502;
 
@@ -99,20 +141,265 @@ impl InputEvent {
     }
 }
 
-/// Virtual pointer device
-pub struct VirtualPointer {
+/// A created uinput device, destroyed again on drop
+struct UinputDevice {
     file: File,
+}
+
+impl UinputDevice {
+    /// Open /dev/uinput ready for capability setup
+    fn open() -> Result<File> {
+        OpenOptions::new()
+            .write(true)
+            .open(UINPUT_PATH)
+            .with_context(|| format!("Failed to open {}. Do you have permission?", UINPUT_PATH))
+    }
+
+    /// Write the device description and create the device
+    fn create(mut file: File, dev: &UinputUserDev) -> Result<Self> {
+        file.write_all(bytemuck::bytes_of(dev))
+            .context("Failed to write device info")?;
+
+        unsafe {
+            if libc::ioctl(file.as_raw_fd(), UI_DEV_CREATE) < 0 {
+                anyhow::bail!("Failed to create uinput device");
+            }
+        }
+
+        // Give the system time to register the device
+        thread::sleep(Duration::from_millis(100));
+
+        Ok(Self { file })
+    }
+
+    /// Write an event to the device
+    fn write_event(&mut self, type_: u16, code: u16, value: i32) -> Result<()> {
+        let event = InputEvent::new(type_, code, value);
+        self.file.write_all(event.as_bytes())?;
+        Ok(())
+    }
+
+    /// Send a sync event
+    fn sync(&mut self) -> Result<()> {
+        self.write_event(EV_SYN, SYN_REPORT, 0)
+    }
+}
+
+impl Drop for UinputDevice {
+    fn drop(&mut self) {
+        unsafe {
+            libc::ioctl(self.file.as_raw_fd(), UI_DEV_DESTROY);
+        }
+    }
+}
+
+/// Get the evdev code for a mouse button (also used by Wayland protocols)
+pub(crate) fn button_code(button: MouseButton) -> u16 {
+    match button {
+        MouseButton::Left => BTN_LEFT,
+        MouseButton::Middle => BTN_MIDDLE,
+        MouseButton::Right => BTN_RIGHT,
+        MouseButton::Side => BTN_SIDE,
+        MouseButton::Extra => BTN_EXTRA,
+        MouseButton::Forward => BTN_FORWARD,
+        MouseButton::Back => BTN_BACK,
+    }
+}
+
+/// Desktop area covered by absolute positioning, in global coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DesktopExtent {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl DesktopExtent {
+    /// Translate a global position into the extent, clamped to its edges
+    pub fn local(&self, x: i32, y: i32) -> (u32, u32) {
+        (
+            (x - self.x).clamp(0, self.width.saturating_sub(1) as i32) as u32,
+            (y - self.y).clamp(0, self.height.saturating_sub(1) as i32) as u32,
+        )
+    }
+
+    /// Map a global position onto an axis range of `0..=max`, aiming at the
+    /// centre of the pixel so rounding in the compositor lands on it
+    pub fn scale(&self, x: i32, y: i32, max: i32) -> (i32, i32) {
+        let (x, y) = self.local(x, y);
+        let axis = |pos: u32, size: u32| {
+            let size = size.max(1) as i64;
+            ((2 * pos as i64 + 1) * (max as i64 + 1) / (2 * size)) as i32
+        };
+        (axis(x, self.width), axis(y, self.height))
+    }
+}
+
+/// Operations kwarpd needs from whatever injects pointer events
+pub trait PointerBackend {
+    /// Short name for log messages
+    fn name(&self) -> &'static str;
+
+    /// Move the pointer by a relative amount
+    fn move_mouse(&mut self, dx: i32, dy: i32) -> Result<()>;
+
+    /// Set the bounding box of all outputs, used to map absolute warps
+    fn set_desktop_extent(&mut self, x: i32, y: i32, width: u32, height: u32);
+
+    /// Warp the pointer to a position in global compositor coordinates
+    fn warp_to(&mut self, x: i32, y: i32) -> Result<()>;
+
+    /// Press or release a mouse button
+    fn button(&mut self, button: MouseButton, pressed: bool) -> Result<()>;
+
+    /// Scroll by 120ths of a notch (dx positive = right, dy positive = down)
+    fn scroll(&mut self, dx: i32, dy: i32) -> Result<()>;
+}
+
+/// Open the configured pointer backend
+///
+/// `auto` keeps uinput, which every compositor accepts, and falls back to
+/// the Wayland protocols when /dev/uinput isn't writable, trying KWin's own
+/// fake input first.
+pub fn open_pointer(kind: PointerBackendKind) -> Result<Pointer> {
+    let candidates = match kind {
+        PointerBackendKind::Auto => vec![
+            PointerBackendKind::Uinput,
+            PointerBackendKind::Kde,
+            PointerBackendKind::Wlr,
+        ],
+        kind => vec![kind],
+    };
+
+    let mut errors = Vec::new();
+    for candidate in candidates {
+        let backend: Result<Box<dyn PointerBackend>> = match candidate {
+            PointerBackendKind::Uinput => VirtualPointer::new().map(|p| Box::new(p) as _),
+            PointerBackendKind::Kde => KdePointer::new().map(|p| Box::new(p) as _),
+            PointerBackendKind::Wlr => WlrPointer::new().map(|p| Box::new(p) as _),
+            PointerBackendKind::Auto => continue,
+        };
+        match backend {
+            Ok(backend) => {
+                log::info!("Using {} pointer backend", backend.name());
+                return Ok(Pointer::new(backend));
+            }
+            Err(e) => {
+                log::debug!("Pointer backend {} unavailable: {:#}", candidate, e);
+                errors.push(format!("{}: {:#}", candidate, e));
+            }
+        }
+    }
+    anyhow::bail!("No pointer backend available ({})", errors.join("; "))
+}
+
+/// Pointer with click and drag handling on top of a backend
+pub struct Pointer {
+    backend: Box<dyn PointerBackend>,
     drag_button_held: bool,
 }
 
+impl Pointer {
+    pub fn new(backend: Box<dyn PointerBackend>) -> Self {
+        Self {
+            backend,
+            drag_button_held: false,
+        }
+    }
+
+    /// Move the mouse by relative amount
+    pub fn move_mouse(&mut self, dx: i32, dy: i32) -> Result<()> {
+        self.backend.move_mouse(dx, dy)
+    }
+
+    /// Set the bounding box of all outputs used to map absolute warps
+    pub fn set_desktop_extent(&mut self, x: i32, y: i32, width: u32, height: u32) {
+        self.backend.set_desktop_extent(x, y, width, height);
+    }
+
+    /// Warp the pointer to a position in global compositor coordinates
+    pub fn warp_to(&mut self, x: i32, y: i32) -> Result<()> {
+        self.backend.warp_to(x, y)
+    }
+
+    /// Scroll by 120ths of a notch (dx positive = right, dy positive = down)
+    pub fn scroll(&mut self, dx: i32, dy: i32) -> Result<()> {
+        self.backend.scroll(dx, dy)
+    }
+
+    /// Click a mouse button
+    pub fn click(&mut self, button: MouseButton) -> Result<()> {
+        self.backend.button(button, true)?;
+        thread::sleep(Duration::from_millis(10));
+        self.backend.button(button, false)
+    }
+
+    /// Toggle drag mode (hold/release left button)
+    pub fn toggle_drag(&mut self) -> Result<bool> {
+        self.drag_button_held = !self.drag_button_held;
+        self.backend.button(MouseButton::Left, self.drag_button_held)?;
+        log::debug!("Drag mode: {}", self.drag_button_held);
+        Ok(self.drag_button_held)
+    }
+
+    /// Release drag if active
+    pub fn release_drag(&mut self) -> Result<()> {
+        if self.drag_button_held {
+            self.drag_button_held = false;
+            self.backend.button(MouseButton::Left, false)?;
+        }
+        Ok(())
+    }
+
+    /// Check if drag is active
+    #[allow(dead_code)]
+    pub fn is_dragging(&self) -> bool {
+        self.drag_button_held
+    }
+}
+
+impl Drop for Pointer {
+    fn drop(&mut self) {
+        // Release any held buttons before the backend goes away
+        let _ = self.release_drag();
+    }
+}
+
+/// Virtual pointer device
+pub struct VirtualPointer {
+    device: UinputDevice,
+    /// Tablet-style device used for absolute warps
+    absolute: UinputDevice,
+    extent: Option<DesktopExtent>,
+    wheel: WheelAccumulator,
+    hwheel: WheelAccumulator,
+}
+
+/// Turns high-resolution wheel motion into whole notches for legacy clients
+#[derive(Debug, Default)]
+pub(crate) struct WheelAccumulator {
+    remainder: i32,
+}
+
+impl WheelAccumulator {
+    /// Add high-resolution units and return the whole notches completed
+    pub fn add(&mut self, units: i32) -> i32 {
+        // Changing direction drops the partial notch, like a real wheel
+        if units.signum() != 0 && units.signum() == -self.remainder.signum() {
+            self.remainder = 0;
+        }
+        self.remainder += units;
+        let notches = self.remainder / WHEEL_UNITS_PER_NOTCH;
+        self.remainder -= notches * WHEEL_UNITS_PER_NOTCH;
+        notches
+    }
+}
+
 impl VirtualPointer {
     /// Create a new virtual pointer device
     pub fn new() -> Result<Self> {
-        let file = OpenOptions::new()
-            .write(true)
-            .open(UINPUT_PATH)
-            .with_context(|| format!("Failed to open {}. Do you have permission?", UINPUT_PATH))?;
-
+        let file = UinputDevice::open()?;
         let fd = file.as_raw_fd();
 
         // Set up event types we support
@@ -127,14 +414,10 @@ impl VirtualPointer {
             }
 
             // Set up mouse buttons
-            if libc::ioctl(fd, UI_SET_KEYBIT, BTN_LEFT as i32) < 0 {
-                anyhow::bail!("Failed to set BTN_LEFT");
-            }
-            if libc::ioctl(fd, UI_SET_KEYBIT, BTN_RIGHT as i32) < 0 {
-                anyhow::bail!("Failed to set BTN_RIGHT");
-            }
-            if libc::ioctl(fd, UI_SET_KEYBIT, BTN_MIDDLE as i32) < 0 {
-                anyhow::bail!("Failed to set BTN_MIDDLE");
+            for code in POINTER_BUTTONS {
+                if libc::ioctl(fd, UI_SET_KEYBIT, code as i32) < 0 {
+                    anyhow::bail!("Failed to set button {:#x}", code);
+                }
             }
 
             // Set up relative axes
@@ -150,6 +433,12 @@ impl VirtualPointer {
             if libc::ioctl(fd, UI_SET_RELBIT, REL_HWHEEL as i32) < 0 {
                 anyhow::bail!("Failed to set REL_HWHEEL");
             }
+            if libc::ioctl(fd, UI_SET_RELBIT, REL_WHEEL_HI_RES as i32) < 0 {
+                anyhow::bail!("Failed to set REL_WHEEL_HI_RES");
+            }
+            if libc::ioctl(fd, UI_SET_RELBIT, REL_HWHEEL_HI_RES as i32) < 0 {
+                anyhow::bail!("Failed to set REL_HWHEEL_HI_RES");
+            }
         }
 
         // Set up device info
@@ -157,45 +446,79 @@ impl VirtualPointer {
         let name = b"kwarpd virtual pointer";
         dev.name[..name.len()].copy_from_slice(name);
 
-        // Write device info
-        let dev_bytes = bytemuck::bytes_of(&dev);
+        let device = UinputDevice::create(file, &dev)?;
 
-        let mut file = file;
-        file.write_all(dev_bytes)
-            .context("Failed to write device info")?;
+        // Created up front so the compositor has picked it up by the first warp
+        let absolute =
+            Self::create_absolute().context("Failed to create absolute pointer device")?;
+
+        log::info!("Created virtual pointer device");
+
+        Ok(Self {
+            device,
+            absolute,
+            extent: None,
+            wheel: WheelAccumulator::default(),
+            hwheel: WheelAccumulator::default(),
+        })
+    }
+
+    /// Create the absolute positioning device
+    ///
+    /// The device advertises ABS_X/ABS_Y plus BTN_LEFT so that libinput treats
+    /// it as an absolute pointer (like a VM tablet) and the compositor maps its
+    /// full range onto the whole desktop.
+    fn create_absolute() -> Result<UinputDevice> {
+        let file = UinputDevice::open()?;
+        let fd = file.as_raw_fd();
 
-        // Create the device
         unsafe {
-            if libc::ioctl(file.as_raw_fd(), UI_DEV_CREATE) < 0 {
-                anyhow::bail!("Failed to create uinput device");
+            if libc::ioctl(fd, UI_SET_EVBIT, EV_KEY as i32) < 0 {
+                anyhow::bail!("Failed to set EV_KEY");
+            }
+            if libc::ioctl(fd, UI_SET_EVBIT, EV_ABS as i32) < 0 {
+                anyhow::bail!("Failed to set EV_ABS");
+            }
+            if libc::ioctl(fd, UI_SET_KEYBIT, BTN_LEFT as i32) < 0 {
+                anyhow::bail!("Failed to set BTN_LEFT");
+            }
+            if libc::ioctl(fd, UI_SET_ABSBIT, ABS_X as i32) < 0 {
+                anyhow::bail!("Failed to set ABS_X");
+            }
+            if libc::ioctl(fd, UI_SET_ABSBIT, ABS_Y as i32) < 0 {
+                anyhow::bail!("Failed to set ABS_Y");
             }
         }
 
-        // Give the system time to register the device
-        thread::sleep(Duration::from_millis(100));
-
-        log::info!("Created virtual pointer device");
+        let mut dev = UinputUserDev::default();
+        let name = b"kwarpd absolute pointer";
+        dev.name[..name.len()].copy_from_slice(name);
+        dev.id_product = 0x5679;
+        dev.absmin[ABS_X as usize] = 0;
+        dev.absmax[ABS_X as usize] = ABS_MAX;
+        dev.absmin[ABS_Y as usize] = 0;
+        dev.absmax[ABS_Y as usize] = ABS_MAX;
 
-        Ok(Self {
-            file,
-            drag_button_held: false,
-        })
+        UinputDevice::create(file, &dev)
     }
 
     /// Write an event to the device
     fn write_event(&mut self, type_: u16, code: u16, value: i32) -> Result<()> {
-        let event = InputEvent::new(type_, code, value);
-        self.file.write_all(event.as_bytes())?;
-        Ok(())
+        self.device.write_event(type_, code, value)
     }
 
     /// Send a sync event
     fn sync(&mut self) -> Result<()> {
-        self.write_event(EV_SYN, SYN_REPORT, 0)
+        self.device.sync()
     }
+}
 
-    /// Move the mouse by relative amount
-    pub fn move_mouse(&mut self, dx: i32, dy: i32) -> Result<()> {
+impl PointerBackend for VirtualPointer {
+    fn name(&self) -> &'static str {
+        "uinput"
+    }
+
+    fn move_mouse(&mut self, dx: i32, dy: i32) -> Result<()> {
         if dx != 0 {
             self.write_event(EV_REL, REL_X, dx)?;
         }
@@ -205,86 +528,143 @@ impl VirtualPointer {
         self.sync()
     }
 
-    /// Click a mouse button (0=left, 1=middle, 2=right)
-    pub fn click(&mut self, button: u8) -> Result<()> {
-        let code = match button {
-            0 => BTN_LEFT,
-            1 => BTN_MIDDLE,
-            2 => BTN_RIGHT,
-            _ => anyhow::bail!("Invalid button: {}", button),
-        };
-
-        // Press
-        self.write_event(EV_KEY, code, 1)?;
-        self.sync()?;
+    /// Only changes how warps are scaled; the absolute device stays as it is
+    fn set_desktop_extent(&mut self, x: i32, y: i32, width: u32, height: u32) {
+        let extent = DesktopExtent { x, y, width, height };
+        if self.extent != Some(extent) {
+            log::debug!("Desktop extent: {}x{}+{}+{}", width, height, x, y);
+            self.extent = Some(extent);
+        }
+    }
 
-        // Small delay
-        thread::sleep(Duration::from_millis(10));
+    /// The kernel drops absolute values that match the last one sent, which
+    /// would lose a warp back to where the device last put the pointer after
+    /// it moved relatively. Each axis is first nudged by one unit in the same
+    /// frame so the final value always goes through.
+    fn warp_to(&mut self, x: i32, y: i32) -> Result<()> {
+        let extent = self
+            .extent
+            .context("Desktop extent unknown, cannot warp pointer")?;
+
+        let (x, y) = extent.scale(x, y, ABS_MAX);
+        for (code, value) in [(ABS_X, x), (ABS_Y, y)] {
+            let nudged = if value < ABS_MAX { value + 1 } else { value - 1 };
+            self.absolute.write_event(EV_ABS, code, nudged)?;
+            self.absolute.write_event(EV_ABS, code, value)?;
+        }
+        self.absolute.sync()
+    }
 
-        // Release
-        self.write_event(EV_KEY, code, 0)?;
+    fn button(&mut self, button: MouseButton, pressed: bool) -> Result<()> {
+        let code = button_code(button);
+        self.write_event(EV_KEY, code, if pressed { 1 } else { 0 })?;
         self.sync()
     }
 
-    /// Press or release a mouse button
-    pub fn button(&mut self, button: u8, pressed: bool) -> Result<()> {
-        let code = match button {
-            0 => BTN_LEFT,
-            1 => BTN_MIDDLE,
-            2 => BTN_RIGHT,
-            _ => anyhow::bail!("Invalid button: {}", button),
-        };
-
-        self.write_event(EV_KEY, code, if pressed { 1 } else { 0 })?;
+    /// Like a real high-resolution mouse, this emits REL_WHEEL_HI_RES and
+    /// REL_HWHEEL_HI_RES for smooth scrolling, plus REL_WHEEL and REL_HWHEEL
+    /// whenever a whole notch adds up.
+    fn scroll(&mut self, dx: i32, dy: i32) -> Result<()> {
+        // The vertical wheel counts up as positive
+        if dy != 0 {
+            let notches = self.wheel.add(dy);
+            self.write_event(EV_REL, REL_WHEEL_HI_RES, -dy)?;
+            if notches != 0 {
+                self.write_event(EV_REL, REL_WHEEL, -notches)?;
+            }
+        }
+        if dx != 0 {
+            let notches = self.hwheel.add(dx);
+            self.write_event(EV_REL, REL_HWHEEL_HI_RES, dx)?;
+            if notches != 0 {
+                self.write_event(EV_REL, REL_HWHEEL, notches)?;
+            }
+        }
         self.sync()
     }
+}
 
-    /// Toggle drag mode (hold/release left button)
-    pub fn toggle_drag(&mut self) -> Result<bool> {
-        self.drag_button_held = !self.drag_button_held;
-        self.button(0, self.drag_button_held)?;
-        log::debug!("Drag mode: {}", self.drag_button_held);
-        Ok(self.drag_button_held)
+impl Drop for VirtualPointer {
+    fn drop(&mut self) {
+        log::info!("Destroyed virtual pointer device");
     }
+}
 
-    /// Release drag if active
-    pub fn release_drag(&mut self) -> Result<()> {
-        if self.drag_button_held {
-            self.drag_button_held = false;
-            self.button(0, false)?;
+/// Virtual keyboard device used to type key chords
+pub struct VirtualKeyboard {
+    device: UinputDevice,
+}
+
+impl VirtualKeyboard {
+    /// Create a new virtual keyboard device
+    pub fn new() -> Result<Self> {
+        let file = UinputDevice::open()?;
+        let fd = file.as_raw_fd();
+
+        unsafe {
+            if libc::ioctl(fd, UI_SET_EVBIT, EV_KEY as i32) < 0 {
+                anyhow::bail!("Failed to set EV_KEY");
+            }
+            for code in 1..BTN_MISC {
+                if libc::ioctl(fd, UI_SET_KEYBIT, code as i32) < 0 {
+                    anyhow::bail!("Failed to set key bit {}", code);
+                }
+            }
         }
-        Ok(())
-    }
 
-    /// Scroll the mouse wheel
-    pub fn scroll(&mut self, amount: i32) -> Result<()> {
-        // Negative amount = scroll up, positive = scroll down
-        self.write_event(EV_REL, REL_WHEEL, -amount)?;
-        self.sync()
+        let mut dev = UinputUserDev::default();
+        let name = b"kwarpd virtual keyboard";
+        dev.name[..name.len()].copy_from_slice(name);
+        dev.id_product = 0x567a;
+
+        let device = UinputDevice::create(file, &dev)?;
+
+        log::info!("Created virtual keyboard device");
+
+        Ok(Self { device })
     }
 
-    /// Horizontal scroll
-    pub fn hscroll(&mut self, amount: i32) -> Result<()> {
-        self.write_event(EV_REL, REL_HWHEEL, amount)?;
-        self.sync()
+    /// Press or release a single key
+    pub fn key(&mut self, code: KeyCode, pressed: bool) -> Result<()> {
+        self.device
+            .write_event(EV_KEY, code.code(), if pressed { 1 } else { 0 })?;
+        self.device.sync()
     }
 
-    /// Check if drag is active
-    pub fn is_dragging(&self) -> bool {
-        self.drag_button_held
+    /// Type a chord: press the modifiers, tap the key, release the modifiers
+    pub fn send_chord(&mut self, key: KeyCode, chord_modifiers: &Modifiers) -> Result<()> {
+        let mut modifiers = Vec::new();
+        if chord_modifiers.ctrl {
+            modifiers.push(KeyCode::KEY_LEFTCTRL);
+        }
+        if chord_modifiers.alt {
+            modifiers.push(KeyCode::KEY_LEFTALT);
+        }
+        if chord_modifiers.shift {
+            modifiers.push(KeyCode::KEY_LEFTSHIFT);
+        }
+        if chord_modifiers.super_key {
+            modifiers.push(KeyCode::KEY_LEFTMETA);
+        }
+
+        for &modifier in &modifiers {
+            self.key(modifier, true)?;
+        }
+
+        self.key(key, true)?;
+        thread::sleep(Duration::from_millis(10));
+        self.key(key, false)?;
+
+        for &modifier in modifiers.iter().rev() {
+            self.key(modifier, false)?;
+        }
+        Ok(())
     }
 }
 
-impl Drop for VirtualPointer {
+impl Drop for VirtualKeyboard {
     fn drop(&mut self) {
-        // Release any held buttons
-        let _ = self.release_drag();
-
-        // Destroy the device
-        unsafe {
-            libc::ioctl(self.file.as_raw_fd(), UI_DEV_DESTROY);
-        }
-        log::info!("Destroyed virtual pointer device");
+        log::info!("Destroyed virtual keyboard device");
     }
 }
 
@@ -300,4 +680,41 @@ mod tests {
         let pointer = VirtualPointer::new();
         assert!(pointer.is_ok());
     }
+
+    #[test]
+    #[ignore]
+    fn test_create_virtual_keyboard() {
+        use super::*;
+        let keyboard = VirtualKeyboard::new();
+        assert!(keyboard.is_ok());
+    }
+
+    #[test]
+    fn test_wheel_accumulator() {
+        use super::*;
+        let mut wheel = WheelAccumulator::default();
+        assert_eq!(wheel.add(50), 0);
+        assert_eq!(wheel.add(50), 0);
+        assert_eq!(wheel.add(50), 1);
+        assert_eq!(wheel.add(300), 2);
+        // 30 left over from the way down is dropped when reversing
+        assert_eq!(wheel.add(-100), 0);
+        assert_eq!(wheel.add(-20), -1);
+    }
+
+    #[test]
+    fn test_extent_scale() {
+        use super::*;
+        let extent = DesktopExtent { x: -1920, y: 0, width: 3840, height: 1080 };
+        assert_eq!(extent.scale(-1920, 0, ABS_MAX), (8, 30));
+        assert_eq!(extent.scale(1919, 1079, ABS_MAX), (65527, 65505));
+        // Positions off the desktop are clamped to its edges
+        assert_eq!(extent.scale(5000, -10, ABS_MAX), (65527, 30));
+
+        // Every pixel maps back to itself under the compositor's scaling
+        for px in 0..3840 {
+            let (abs, _) = extent.scale(px - 1920, 0, ABS_MAX);
+            assert_eq!(abs as i64 * 3840 / (ABS_MAX as i64 + 1), px as i64);
+        }
+    }
 }
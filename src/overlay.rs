@@ -1,10 +1,11 @@
 //! KWarpd Overlay Module
 //!
-//! Handles the Wayland overlay window for hint mode using layer-shell
+//! Handles the Wayland overlay window for hint and grid mode, the cursor
+//! indicator for normal mode and the mode badge, using layer-shell
 
 use anyhow::{Context, Result};
 use smithay_client_toolkit::{
-    compositor::{CompositorHandler, CompositorState},
+    compositor::{CompositorHandler, CompositorState, Region},
     delegate_compositor, delegate_layer, delegate_output, delegate_registry, delegate_shm,
     output::{OutputHandler, OutputState},
     registry::{ProvidesRegistryState, RegistryState},
@@ -18,14 +19,23 @@ use smithay_client_toolkit::{
     },
     shm::{Shm, ShmHandler, slot::SlotPool},
 };
+use std::os::fd::{AsFd, OwnedFd};
 use std::sync::Arc;
+use std::time::Duration;
 use wayland_client::{
-    Connection, QueueHandle,
+    Connection, EventQueue, QueueHandle,
+    backend::WaylandError,
     globals::registry_queue_init,
     protocol::{wl_output, wl_shm, wl_surface},
 };
 
-use crate::config::Config;
+use crate::config::{BadgePosition, Config};
+
+/// Padding around label text
+const LABEL_PADDING: i32 = 4;
+
+/// Distance between the mode badge and the screen edges
+const BADGE_MARGIN: i32 = 10;
 
 /// Hint point on the screen
 #[derive(Debug, Clone)]
@@ -35,50 +45,210 @@ pub struct HintPoint {
     pub label: String,
 }
 
-/// Calculate hint grid positions
-pub fn calculate_hints(
-    width: u32,
-    height: u32,
+/// A screen area in global compositor coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScreenRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ScreenRect {
+    /// Check if a global point lies on this screen
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x
+            && y >= self.y
+            && x < self.x + self.width as i32
+            && y < self.y + self.height as i32
+    }
+
+    /// Smallest rectangle covering all given screens
+    pub fn bounding(screens: &[ScreenRect]) -> Option<ScreenRect> {
+        let first = screens.first()?;
+        let (mut x0, mut y0) = (first.x, first.y);
+        let (mut x1, mut y1) = (first.x + first.width as i32, first.y + first.height as i32);
+        for s in &screens[1..] {
+            x0 = x0.min(s.x);
+            y0 = y0.min(s.y);
+            x1 = x1.max(s.x + s.width as i32);
+            y1 = y1.max(s.y + s.height as i32);
+        }
+        Some(ScreenRect {
+            x: x0,
+            y: y0,
+            width: (x1 - x0) as u32,
+            height: (y1 - y0) as u32,
+        })
+    }
+}
+
+/// Calculate hint grid positions across all screens
+///
+/// The points two-character labels allow are shared out between the screens
+/// by area, each spread as a grid over its screen, and labelled from a single
+/// sequence so that any point is reachable by typing one label. A screen too
+/// small for its share at `hint_size` gets fewer points, so labels never
+/// overlap.
+pub fn calculate_screen_hints(
+    screens: &[ScreenRect],
     hint_chars: &str,
-    _hint_size: u32,
+    hint_size: u32,
 ) -> Vec<HintPoint> {
     let chars: Vec<char> = hint_chars.chars().collect();
     let num_chars = chars.len();
+    if num_chars == 0 {
+        return Vec::new();
+    }
 
-    // For two-character hints, we have num_chars^2 total hints
-    let total_hints = num_chars * num_chars;
+    // Room for a two-character label with its padding, and as much again
+    // between neighbours
+    let (label_width, label_height) = label_size("aa", hint_size as f32);
+    let min_spacing = (
+        (label_width + LABEL_PADDING * 4) as u32,
+        (label_height + LABEL_PADDING * 4) as u32,
+    );
 
-    // Calculate grid dimensions
-    let aspect = width as f64 / height as f64;
-    let hints_y = ((total_hints as f64) / aspect).sqrt().ceil() as u32;
-    let hints_x = ((total_hints as f64) * aspect).sqrt().ceil() as u32;
+    let budget = split_by_area(screens, num_chars * num_chars);
+    let points: Vec<(i32, i32)> = screens
+        .iter()
+        .zip(budget)
+        .flat_map(|(screen, count)| grid_points(screen, count, min_spacing))
+        .collect();
 
-    let spacing_x = width / (hints_x + 1);
-    let spacing_y = height / (hints_y + 1);
+    let labels = hint_labels(&chars, points.len());
 
-    let mut hints = Vec::new();
-    let mut hint_idx = 0;
+    points
+        .into_iter()
+        .zip(labels)
+        .map(|((x, y), label)| HintPoint { x, y, label })
+        .collect()
+}
+
+/// Split `total` between the screens in proportion to their area
+///
+/// Whole shares go out first and what is left goes to the screens with the
+/// largest fractions, so the counts always add up to `total`.
+fn split_by_area(screens: &[ScreenRect], total: usize) -> Vec<usize> {
+    let areas: Vec<u64> = screens
+        .iter()
+        .map(|s| s.width as u64 * s.height as u64)
+        .collect();
+    let total_area: u64 = areas.iter().sum();
+    if total_area == 0 {
+        return vec![0; screens.len()];
+    }
+
+    let mut counts: Vec<usize> = areas
+        .iter()
+        .map(|&area| (area * total as u64 / total_area) as usize)
+        .collect();
+    let mut by_fraction: Vec<usize> = (0..screens.len()).collect();
+    by_fraction.sort_by_key(|&i| std::cmp::Reverse(areas[i] * total as u64 % total_area));
+
+    let left = total - counts.iter().sum::<usize>();
+    for &i in by_fraction.iter().take(left) {
+        counts[i] += 1;
+    }
+    counts
+}
+
+/// Spread up to `count` points evenly over a screen, no closer than
+/// `min_spacing` apart
+fn grid_points(screen: &ScreenRect, count: usize, min_spacing: (u32, u32)) -> Vec<(i32, i32)> {
+    if screen.width == 0 || screen.height == 0 {
+        return Vec::new();
+    }
+
+    // Calculate grid dimensions, with the screen edges as outer grid lines
+    let aspect = screen.width as f64 / screen.height as f64;
+    let max_x = (screen.width / min_spacing.0.max(1)).saturating_sub(1);
+    let max_y = (screen.height / min_spacing.1.max(1)).saturating_sub(1);
+    let hints_y = (((count as f64) / aspect).sqrt().ceil() as u32).min(max_y);
+    let hints_x = (((count as f64) * aspect).sqrt().ceil() as u32).min(max_x);
+
+    let spacing_x = screen.width / (hints_x + 1);
+    let spacing_y = screen.height / (hints_y + 1);
+
+    let mut points = Vec::with_capacity(count);
 
     for row in 0..hints_y {
         for col in 0..hints_x {
-            if hint_idx >= total_hints {
+            if points.len() >= count {
                 break;
             }
 
-            let x = ((col + 1) * spacing_x) as i32;
-            let y = ((row + 1) * spacing_y) as i32;
+            let x = screen.x + ((col + 1) * spacing_x) as i32;
+            let y = screen.y + ((row + 1) * spacing_y) as i32;
+            points.push((x, y));
+        }
+    }
+
+    points
+}
 
-            // Generate two-character label
-            let first_char = chars[hint_idx / num_chars];
-            let second_char = chars[hint_idx % num_chars];
-            let label = format!("{}{}", first_char, second_char);
+/// Generate `count` labels of equal length from the hint characters
+///
+/// Labels have the shortest length that gives every point a unique label,
+/// so no label is a prefix of another.
+fn hint_labels(chars: &[char], count: usize) -> Vec<String> {
+    let num_chars = chars.len();
+    let mut len = 1;
+    let mut capacity = num_chars;
+    while capacity < count && num_chars > 1 {
+        len += 1;
+        capacity *= num_chars;
+    }
 
-            hints.push(HintPoint { x, y, label });
-            hint_idx += 1;
-        }
+    (0..count.min(capacity))
+        .map(|mut idx| {
+            let mut label = vec![chars[0]; len];
+            for slot in label.iter_mut().rev() {
+                *slot = chars[idx % num_chars];
+                idx /= num_chars;
+            }
+            label.into_iter().collect()
+        })
+        .collect()
+}
+
+/// Grid shown in grid mode
+#[derive(Debug, Clone)]
+pub struct GridView {
+    pub region: ScreenRect,
+    pub rows: u32,
+    pub columns: u32,
+    /// One key per cell, row by row
+    pub keys: Vec<char>,
+}
+
+impl GridView {
+    /// Width and height of a single cell
+    fn cell_size(&self) -> (f64, f64) {
+        (
+            self.region.width as f64 / self.columns as f64,
+            self.region.height as f64 / self.rows as f64,
+        )
     }
 
-    hints
+    /// Key labels placed at the centre of each cell
+    pub fn labels(&self) -> Vec<HintPoint> {
+        let (cell_w, cell_h) = self.cell_size();
+        self.keys
+            .iter()
+            .take(self.rows as usize * self.columns as usize)
+            .enumerate()
+            .map(|(i, key)| {
+                let row = (i as u32 / self.columns) as f64;
+                let col = (i as u32 % self.columns) as f64;
+                HintPoint {
+                    x: self.region.x + ((col + 0.5) * cell_w) as i32,
+                    y: self.region.y + ((row + 0.5) * cell_h) as i32,
+                    label: key.to_string(),
+                }
+            })
+            .collect()
+    }
 }
 
 /// Find a hint by its label prefix
@@ -94,6 +264,11 @@ pub fn find_hint_exact<'a>(hints: &'a [HintPoint], label: &str) -> Option<&'a Hi
     hints.iter().find(|h| h.label == label)
 }
 
+/// Approximate size of a label's text, before padding
+fn label_size(label: &str, font_size: f32) -> (i32, i32) {
+    ((label.len() as f32 * font_size * 0.6) as i32, font_size as i32)
+}
+
 /// Draw hints onto a pixel buffer (ARGB8888 format)
 /// This is a simplified version that draws directly to the buffer without tiny-skia conflicts
 pub fn draw_hints(
@@ -141,9 +316,8 @@ pub fn draw_hints(
             (255u8, 255u8, 255u8) // White
         };
 
-        let text_width = (hint.label.len() as f32 * font_size * 0.6) as i32;
-        let text_height = font_size as i32;
-        let padding = 4i32;
+        let (text_width, text_height) = label_size(&hint.label, font_size);
+        let padding = LABEL_PADDING;
 
         let rect_x = hint.x - text_width / 2 - padding;
         let rect_y = hint.y - text_height / 2 - padding;
@@ -203,6 +377,93 @@ pub fn draw_hints(
     }
 }
 
+/// Fill a rectangle with an RGBA color, clipped to the buffer (ARGB8888 format)
+fn fill_rect(buffer: &mut [u8], width: u32, height: u32, rect: (i32, i32, i32, i32), rgba: u32) {
+    let (x, y, w, h) = rect;
+    let [r, g, b, a] = rgba.to_be_bytes();
+
+    for py in y.max(0)..(y + h).min(height as i32) {
+        for px in x.max(0)..(x + w).min(width as i32) {
+            let idx = ((py as u32 * width + px as u32) * 4) as usize;
+            if idx + 3 < buffer.len() {
+                buffer[idx] = b;
+                buffer[idx + 1] = g;
+                buffer[idx + 2] = r;
+                buffer[idx + 3] = a;
+            }
+        }
+    }
+}
+
+/// Draw grid lines and the region border onto a pixel buffer (ARGB8888 format)
+///
+/// The grid region must already be in buffer coordinates. Cell labels are
+/// drawn separately with `draw_hints`.
+pub fn draw_grid(
+    buffer: &mut [u8],
+    width: u32,
+    height: u32,
+    grid: &GridView,
+    line_color: u32,
+    border_color: u32,
+) {
+    let region = grid.region;
+    let (cell_w, cell_h) = grid.cell_size();
+    let line = 2i32;
+    let border = 3i32;
+
+    // Inner cell boundaries
+    for col in 1..grid.columns {
+        let x = region.x + (col as f64 * cell_w) as i32;
+        let rect = (x - line / 2, region.y, line, region.height as i32);
+        fill_rect(buffer, width, height, rect, line_color);
+    }
+    for row in 1..grid.rows {
+        let y = region.y + (row as f64 * cell_h) as i32;
+        let rect = (region.x, y - line / 2, region.width as i32, line);
+        fill_rect(buffer, width, height, rect, line_color);
+    }
+
+    // Border around the whole region
+    let (rw, rh) = (region.width as i32, region.height as i32);
+    let edges = [
+        (region.x, region.y, rw, border),
+        (region.x, region.y + rh - border, rw, border),
+        (region.x, region.y, border, rh),
+        (region.x + rw - border, region.y, border, rh),
+    ];
+    for rect in edges {
+        fill_rect(buffer, width, height, rect, border_color);
+    }
+}
+
+/// Layer surface covering a single output
+struct OutputSurface {
+    /// Output the surface is bound to, `None` if the compositor announced no outputs
+    output: Option<wl_output::WlOutput>,
+    layer_surface: LayerSurface,
+    rect: ScreenRect,
+    configured: bool,
+}
+
+/// Small layer surface marking the pointer in normal mode
+struct CursorSurface {
+    output: Option<wl_output::WlOutput>,
+    layer_surface: LayerSurface,
+    /// Output the indicator is placed on, in global coordinates
+    screen: ScreenRect,
+    configured: bool,
+}
+
+/// Small layer surface showing the current mode
+struct BadgeSurface {
+    layer_surface: LayerSurface,
+    text: String,
+    width: u32,
+    height: u32,
+    configured: bool,
+}
+
 /// Overlay application state for Wayland
 pub struct OverlayApp {
     registry_state: RegistryState,
@@ -211,11 +472,11 @@ pub struct OverlayApp {
     layer_shell: LayerShell,
     compositor: CompositorState,
     output_state: OutputState,
-    layer_surface: Option<LayerSurface>,
-    width: u32,
-    height: u32,
-    configured: bool,
+    surfaces: Vec<OutputSurface>,
+    cursor: Option<CursorSurface>,
+    badge: Option<BadgeSurface>,
     hints: Vec<HintPoint>,
+    grid: Option<GridView>,
     highlight_prefix: String,
     config: Arc<Config>,
     font_data: Vec<u8>,
@@ -245,11 +506,11 @@ impl OverlayApp {
             layer_shell,
             compositor,
             output_state,
-            layer_surface: None,
-            width: 0,
-            height: 0,
-            configured: false,
+            surfaces: Vec::new(),
+            cursor: None,
+            badge: None,
             hints: Vec::new(),
+            grid: None,
             highlight_prefix: String::new(),
             config,
             font_data,
@@ -257,8 +518,24 @@ impl OverlayApp {
         })
     }
 
-    /// Create and show the overlay surface
+    /// Create and show one overlay surface per output
     pub fn show(&mut self, qh: &QueueHandle<Self>) -> Result<()> {
+        let outputs: Vec<_> = self.output_state.outputs().collect();
+
+        if outputs.is_empty() {
+            // Let the compositor pick an output for us
+            self.create_surface(qh, None);
+        }
+        for output in outputs {
+            self.create_surface(qh, Some(output));
+        }
+
+        self.should_close = false;
+        Ok(())
+    }
+
+    /// Create a layer surface covering the given output
+    fn create_surface(&mut self, qh: &QueueHandle<Self>, output: Option<wl_output::WlOutput>) {
         let surface = self.compositor.create_surface(qh);
 
         let layer_surface = self.layer_shell.create_layer_surface(
@@ -266,7 +543,7 @@ impl OverlayApp {
             surface,
             Layer::Overlay,
             Some("kwarpd-hints"),
-            None,
+            output.as_ref(),
         );
 
         layer_surface.set_anchor(Anchor::TOP | Anchor::BOTTOM | Anchor::LEFT | Anchor::RIGHT);
@@ -274,104 +551,553 @@ impl OverlayApp {
         layer_surface.set_keyboard_interactivity(KeyboardInteractivity::None);
         layer_surface.commit();
 
-        self.layer_surface = Some(layer_surface);
-        Ok(())
+        let rect = output
+            .as_ref()
+            .and_then(|o| self.output_rect(o))
+            .unwrap_or(ScreenRect { x: 0, y: 0, width: 0, height: 0 });
+
+        self.surfaces.push(OutputSurface {
+            output,
+            layer_surface,
+            rect,
+            configured: false,
+        });
+    }
+
+    /// Logical position and size of an output in global coordinates
+    fn output_rect(&self, output: &wl_output::WlOutput) -> Option<ScreenRect> {
+        let info = self.output_state.info(output)?;
+        let (x, y) = info.logical_position.unwrap_or(info.location);
+        let (width, height) = info.logical_size.or_else(|| {
+            info.modes.iter().find(|m| m.current).map(|m| m.dimensions)
+        })?;
+        Some(ScreenRect {
+            x,
+            y,
+            width: width as u32,
+            height: height as u32,
+        })
+    }
+
+    /// Show the cursor indicator centred on a global position
+    ///
+    /// The indicator is moved with its layer-shell margins and recreated when
+    /// the position crosses onto another output.
+    pub fn show_cursor(&mut self, qh: &QueueHandle<Self>, x: i32, y: i32) {
+        let size = self.config.cursor_size;
+        if size == 0 {
+            return;
+        }
+
+        if !self.cursor.as_ref().is_some_and(|c| c.screen.contains(x, y)) {
+            let output = self
+                .output_state
+                .outputs()
+                .find(|o| self.output_rect(o).is_some_and(|r| r.contains(x, y)));
+            self.cursor = self.create_cursor(qh, output);
+        }
+        let Some(cursor) = &self.cursor else {
+            return;
+        };
+
+        // Margins are relative to the output edge since the exclusive zone is -1
+        let half = (size / 2) as i32;
+        let top = (y - cursor.screen.y - half).max(0);
+        let left = (x - cursor.screen.x - half).max(0);
+        cursor.layer_surface.set_margin(top, 0, 0, left);
+        cursor.layer_surface.commit();
+    }
+
+    /// Create the cursor indicator surface on the given output
+    fn create_cursor(
+        &self,
+        qh: &QueueHandle<Self>,
+        output: Option<wl_output::WlOutput>,
+    ) -> Option<CursorSurface> {
+        let screen = output.as_ref().and_then(|o| self.output_rect(o))?;
+        let surface = self.compositor.create_surface(qh);
+
+        // An empty input region lets clicks through to the window underneath
+        match Region::new(&self.compositor) {
+            Ok(region) => surface.set_input_region(Some(region.wl_region())),
+            Err(e) => log::warn!("Failed to create input region: {}", e),
+        }
+
+        let layer_surface = self.layer_shell.create_layer_surface(
+            qh,
+            surface,
+            Layer::Overlay,
+            Some("kwarpd-cursor"),
+            output.as_ref(),
+        );
+
+        let size = self.config.cursor_size;
+        layer_surface.set_anchor(Anchor::TOP | Anchor::LEFT);
+        layer_surface.set_size(size, size);
+        layer_surface.set_exclusive_zone(-1);
+        layer_surface.set_keyboard_interactivity(KeyboardInteractivity::None);
+        layer_surface.commit();
+
+        Some(CursorSurface {
+            output,
+            layer_surface,
+            screen,
+            configured: false,
+        })
+    }
+
+    /// Hide the cursor indicator
+    pub fn hide_cursor(&mut self) {
+        self.cursor = None;
+    }
+
+    /// Show the mode badge with the given text, or hide it for `None`
+    pub fn set_badge(&mut self, qh: &QueueHandle<Self>, text: Option<&str>) {
+        let (Some(text), Some(position)) = (text, self.config.badge_position) else {
+            self.badge = None;
+            return;
+        };
+        if self.badge.as_ref().is_some_and(|b| b.text == text) {
+            return;
+        }
+
+        // Size the surface to fit the label as draw_hints lays it out
+        let (text_width, text_height) = label_size(text, self.config.hint_size as f32);
+        let width = (text_width + LABEL_PADDING * 2) as u32;
+        let height = (text_height + LABEL_PADDING * 2) as u32;
+
+        match &mut self.badge {
+            Some(badge) => {
+                badge.text = text.to_string();
+                if (badge.width, badge.height) == (width, height) {
+                    self.draw_badge();
+                } else {
+                    // Redrawn once the compositor configures the new size
+                    badge.width = width;
+                    badge.height = height;
+                    badge.layer_surface.set_size(width, height);
+                    badge.layer_surface.commit();
+                }
+            }
+            None => {
+                self.badge = Some(self.create_badge(qh, text, position, width, height));
+            }
+        }
+    }
+
+    /// Create the mode badge surface in a corner or at an edge of the screen
+    fn create_badge(
+        &self,
+        qh: &QueueHandle<Self>,
+        text: &str,
+        position: BadgePosition,
+        width: u32,
+        height: u32,
+    ) -> BadgeSurface {
+        let surface = self.compositor.create_surface(qh);
+        match Region::new(&self.compositor) {
+            Ok(region) => surface.set_input_region(Some(region.wl_region())),
+            Err(e) => log::warn!("Failed to create input region: {}", e),
+        }
+
+        // No output given, so the compositor puts it on the active one
+        let layer_surface = self.layer_shell.create_layer_surface(
+            qh,
+            surface,
+            Layer::Overlay,
+            Some("kwarpd-badge"),
+            None,
+        );
+
+        let anchor = match position {
+            BadgePosition::TopLeft => Anchor::TOP | Anchor::LEFT,
+            BadgePosition::Top => Anchor::TOP,
+            BadgePosition::TopRight => Anchor::TOP | Anchor::RIGHT,
+            BadgePosition::BottomLeft => Anchor::BOTTOM | Anchor::LEFT,
+            BadgePosition::Bottom => Anchor::BOTTOM,
+            BadgePosition::BottomRight => Anchor::BOTTOM | Anchor::RIGHT,
+        };
+        layer_surface.set_anchor(anchor);
+        layer_surface.set_size(width, height);
+        layer_surface.set_margin(BADGE_MARGIN, BADGE_MARGIN, BADGE_MARGIN, BADGE_MARGIN);
+        layer_surface.set_keyboard_interactivity(KeyboardInteractivity::None);
+        layer_surface.commit();
+
+        BadgeSurface {
+            layer_surface,
+            text: text.to_string(),
+            width,
+            height,
+            configured: false,
+        }
     }
 
     /// Hide and destroy the overlay
     pub fn hide(&mut self) {
-        self.layer_surface = None;
-        self.configured = false;
+        self.surfaces.clear();
         self.hints.clear();
+        self.grid = None;
         self.highlight_prefix.clear();
     }
 
     /// Set hints to display
     pub fn set_hints(&mut self, hints: Vec<HintPoint>) {
         self.hints = hints;
+        self.grid = None;
         self.highlight_prefix.clear();
+        self.draw();
+    }
+
+    /// Set the grid to display, replacing any hints
+    pub fn set_grid(&mut self, grid: GridView) {
+        self.hints = grid.labels();
+        self.grid = Some(grid);
+        self.highlight_prefix.clear();
+        self.draw();
     }
 
     /// Update highlight prefix
     pub fn set_highlight(&mut self, prefix: &str) {
-        self.highlight_prefix = prefix.to_string();
+        if self.highlight_prefix != prefix {
+            self.highlight_prefix = prefix.to_string();
+            self.draw();
+        }
     }
 
     /// Check if overlay is shown
     pub fn is_shown(&self) -> bool {
-        self.layer_surface.is_some() && self.configured
+        !self.surfaces.is_empty() && !self.is_pending()
+    }
+
+    /// Check if any surface is still waiting for its first configure
+    pub fn is_pending(&self) -> bool {
+        self.surfaces.iter().any(|s| !s.configured)
     }
 
     /// Request close
+    #[allow(dead_code)]
     pub fn request_close(&mut self) {
         self.should_close = true;
     }
 
     /// Check if close was requested
+    #[allow(dead_code)]
     pub fn should_close(&self) -> bool {
         self.should_close
     }
 
-    /// Draw the overlay
-    fn draw(&mut self, _qh: &QueueHandle<Self>) {
-        if !self.configured || self.layer_surface.is_none() {
-            return;
+    /// Draw the overlay on every configured surface
+    fn draw(&mut self) {
+        for surface in &self.surfaces {
+            if !surface.configured {
+                continue;
+            }
+
+            let width = surface.rect.width;
+            let height = surface.rect.height;
+            let stride = width * 4;
+            let size = (stride * height) as usize;
+
+            if self.pool.is_none() {
+                self.pool = SlotPool::new(size, &self.shm).ok();
+            }
+
+            let pool = match &mut self.pool {
+                Some(p) => p,
+                None => return,
+            };
+
+            let (buffer, canvas) = match pool.create_buffer(
+                width as i32,
+                height as i32,
+                stride as i32,
+                wl_shm::Format::Argb8888,
+            ) {
+                Ok((b, c)) => (b, c),
+                Err(_) => continue,
+            };
+
+            // Translate the hints on this output into surface-local coordinates
+            let local_hints: Vec<HintPoint> = self
+                .hints
+                .iter()
+                .filter(|h| surface.rect.contains(h.x, h.y))
+                .map(|h| HintPoint {
+                    x: h.x - surface.rect.x,
+                    y: h.y - surface.rect.y,
+                    label: h.label.clone(),
+                })
+                .collect();
+
+            draw_hints(
+                canvas,
+                width,
+                height,
+                &local_hints,
+                &self.highlight_prefix,
+                self.config.hint_size,
+                &self.font_data,
+            );
+
+            if let Some(grid) = &self.grid {
+                let mut local_grid = grid.clone();
+                local_grid.region.x -= surface.rect.x;
+                local_grid.region.y -= surface.rect.y;
+                draw_grid(
+                    canvas,
+                    width,
+                    height,
+                    &local_grid,
+                    self.config.grid_color,
+                    self.config.grid_border_color,
+                );
+            }
+
+            let layer_surface = &surface.layer_surface;
+            layer_surface
+                .wl_surface()
+                .attach(Some(buffer.wl_buffer()), 0, 0);
+            layer_surface
+                .wl_surface()
+                .damage_buffer(0, 0, width as i32, height as i32);
+            layer_surface.commit();
         }
+    }
 
-        let layer_surface = self.layer_surface.as_ref().unwrap();
+    /// Fill the cursor indicator with the cursor color
+    fn draw_cursor(&mut self) {
+        let Some(cursor) = &self.cursor else {
+            return;
+        };
+        if !cursor.configured {
+            return;
+        }
 
-        let stride = self.width * 4;
-        let size = (stride * self.height) as usize;
+        let size = self.config.cursor_size;
+        let stride = size * 4;
 
         if self.pool.is_none() {
-            self.pool = SlotPool::new(size, &self.shm).ok();
+            self.pool = SlotPool::new((stride * size) as usize, &self.shm).ok();
         }
+        let Some(pool) = &mut self.pool else {
+            return;
+        };
+
+        let Ok((buffer, canvas)) =
+            pool.create_buffer(size as i32, size as i32, stride as i32, wl_shm::Format::Argb8888)
+        else {
+            return;
+        };
+        let rect = (0, 0, size as i32, size as i32);
+        fill_rect(canvas, size, size, rect, self.config.cursor_color);
+
+        let surface = cursor.layer_surface.wl_surface();
+        surface.attach(Some(buffer.wl_buffer()), 0, 0);
+        surface.damage_buffer(0, 0, size as i32, size as i32);
+        cursor.layer_surface.commit();
+    }
 
-        let pool = match &mut self.pool {
-            Some(p) => p,
-            None => return,
+    /// Draw the badge label centred on its surface
+    fn draw_badge(&mut self) {
+        let Some(badge) = &self.badge else {
+            return;
         };
+        if !badge.configured {
+            return;
+        }
+
+        let (width, height) = (badge.width, badge.height);
+        let stride = width * 4;
 
-        let (buffer, canvas) = match pool.create_buffer(
-            self.width as i32,
-            self.height as i32,
-            stride as i32,
-            wl_shm::Format::Argb8888,
-        ) {
-            Ok((b, c)) => (b, c),
-            Err(_) => return,
+        if self.pool.is_none() {
+            self.pool = SlotPool::new((stride * height) as usize, &self.shm).ok();
+        }
+        let Some(pool) = &mut self.pool else {
+            return;
         };
 
+        let Ok((buffer, canvas)) =
+            pool.create_buffer(width as i32, height as i32, stride as i32, wl_shm::Format::Argb8888)
+        else {
+            return;
+        };
+        let label = HintPoint {
+            x: width as i32 / 2,
+            y: height as i32 / 2,
+            label: badge.text.clone(),
+        };
         draw_hints(
             canvas,
-            self.width,
-            self.height,
-            &self.hints,
-            &self.highlight_prefix,
+            width,
+            height,
+            &[label],
+            "",
             self.config.hint_size,
             &self.font_data,
         );
 
-        layer_surface
-            .wl_surface()
-            .attach(Some(buffer.wl_buffer()), 0, 0);
-        layer_surface
-            .wl_surface()
-            .damage_buffer(0, 0, self.width as i32, self.height as i32);
-        layer_surface.commit();
+        let surface = badge.layer_surface.wl_surface();
+        surface.attach(Some(buffer.wl_buffer()), 0, 0);
+        surface.damage_buffer(0, 0, width as i32, height as i32);
+        badge.layer_surface.commit();
     }
 
-    /// Get screen dimensions
-    pub fn get_dimensions(&self) -> (u32, u32) {
-        (self.width, self.height)
+    /// Get the screens covered by configured surfaces, in global coordinates
+    pub fn screens(&self) -> Vec<ScreenRect> {
+        self.surfaces
+            .iter()
+            .filter(|s| s.configured)
+            .map(|s| s.rect)
+            .collect()
     }
 
     /// Get current hints
+    #[allow(dead_code)]
     pub fn get_hints(&self) -> &[HintPoint] {
         &self.hints
     }
 }
 
+/// Wayland connection driving the overlay from the main event loop
+pub struct Overlay {
+    conn: Connection,
+    event_queue: EventQueue<OverlayApp>,
+    qh: QueueHandle<OverlayApp>,
+    app: OverlayApp,
+}
+
+impl Overlay {
+    /// Connect to the compositor and bind the globals needed for the overlay
+    pub fn connect(config: Arc<Config>) -> Result<Self> {
+        let conn = Connection::connect_to_env().context("Failed to connect to Wayland")?;
+        let mut event_queue = conn.new_event_queue();
+        let qh = event_queue.handle();
+        let mut app = OverlayApp::new(&conn, &qh, config)?;
+
+        // Receive the initial output information
+        event_queue
+            .roundtrip(&mut app)
+            .context("Wayland roundtrip failed")?;
+
+        Ok(Self {
+            conn,
+            event_queue,
+            qh,
+            app,
+        })
+    }
+
+    /// Duplicate the connection fd for registration with the event loop
+    pub fn poll_fd(&self) -> Result<OwnedFd> {
+        self.conn
+            .backend()
+            .poll_fd()
+            .as_fd()
+            .try_clone_to_owned()
+            .context("Failed to duplicate Wayland fd")
+    }
+
+    /// Read pending events from the socket and dispatch them
+    pub fn read_events(&mut self) -> Result<()> {
+        if let Some(guard) = self.event_queue.prepare_read() {
+            match guard.read() {
+                Ok(_) => {}
+                Err(WaylandError::Io(e)) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e).context("Failed to read Wayland events"),
+            }
+        }
+        self.dispatch_pending()
+    }
+
+    /// Dispatch queued events and flush outgoing requests
+    pub fn dispatch_pending(&mut self) -> Result<()> {
+        self.event_queue
+            .dispatch_pending(&mut self.app)
+            .context("Failed to dispatch Wayland events")?;
+        self.event_queue
+            .flush()
+            .context("Failed to flush Wayland connection")?;
+        Ok(())
+    }
+
+    /// Show the overlay and wait until the compositor has configured it
+    pub fn show(&mut self) -> Result<()> {
+        if !self.app.is_shown() {
+            self.app.hide();
+            self.app.show(&self.qh)?;
+        }
+        while self.app.is_pending() {
+            self.event_queue
+                .roundtrip(&mut self.app)
+                .context("Wayland roundtrip failed")?;
+        }
+        Ok(())
+    }
+
+    /// Hide the overlay
+    pub fn hide(&mut self) {
+        self.app.hide();
+    }
+
+    /// Show the cursor indicator at a global position
+    pub fn show_cursor(&mut self, x: i32, y: i32) {
+        self.app.show_cursor(&self.qh, x, y);
+    }
+
+    /// Hide the cursor indicator
+    pub fn hide_cursor(&mut self) {
+        self.app.hide_cursor();
+    }
+
+    /// Show the mode badge with the given text, or hide it for `None`
+    pub fn set_badge(&mut self, text: Option<&str>) {
+        self.app.set_badge(&self.qh, text);
+    }
+
+    /// Set hints to display
+    pub fn set_hints(&mut self, hints: Vec<HintPoint>) {
+        self.app.set_hints(hints);
+    }
+
+    /// Set the grid to display
+    pub fn set_grid(&mut self, grid: GridView) {
+        self.app.set_grid(grid);
+    }
+
+    /// Update highlight prefix
+    pub fn set_highlight(&mut self, prefix: &str) {
+        self.app.set_highlight(prefix);
+    }
+
+    /// Get the screens covered by the overlay, in global coordinates
+    pub fn screens(&self) -> Vec<ScreenRect> {
+        self.app.screens()
+    }
+
+    /// Time between frames on the fastest output, if any output reports a refresh rate
+    pub fn frame_interval(&self) -> Option<Duration> {
+        self.app
+            .output_state
+            .outputs()
+            .filter_map(|o| self.app.output_state.info(&o))
+            .flat_map(|info| info.modes.into_iter().filter(|m| m.current))
+            .map(|mode| mode.refresh_rate)
+            .filter(|&millihertz| millihertz > 0)
+            .max()
+            .map(|millihertz| Duration::from_secs(1000) / millihertz as u32)
+    }
+
+    /// Use a reloaded configuration for future drawing
+    pub fn set_config(&mut self, config: Arc<Config>) {
+        self.app.config = config;
+        // Recreated with the new size and color on the next move
+        self.app.hide_cursor();
+        // Recreated now in case the position changed
+        let badge = self.app.badge.take().map(|b| b.text);
+        self.app.set_badge(&self.qh, badge.as_deref());
+    }
+}
+
 impl CompositorHandler for OverlayApp {
     fn scale_factor_changed(
         &mut self,
@@ -394,11 +1120,11 @@ impl CompositorHandler for OverlayApp {
     fn frame(
         &mut self,
         _conn: &Connection,
-        qh: &QueueHandle<Self>,
+        _qh: &QueueHandle<Self>,
         _surface: &wl_surface::WlSurface,
         _time: u32,
     ) {
-        self.draw(qh);
+        self.draw();
     }
 
     fn surface_enter(
@@ -445,44 +1171,79 @@ impl OutputHandler for OverlayApp {
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _output: wl_output::WlOutput,
+        output: wl_output::WlOutput,
     ) {
+        self.surfaces.retain(|s| s.output.as_ref() != Some(&output));
+        if self.cursor.as_ref().is_some_and(|c| c.output.as_ref() == Some(&output)) {
+            self.cursor = None;
+        }
     }
 }
 
 impl LayerShellHandler for OverlayApp {
-    fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _layer: &LayerSurface) {
-        self.should_close = true;
+    fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, layer: &LayerSurface) {
+        self.surfaces
+            .retain(|s| s.layer_surface.wl_surface() != layer.wl_surface());
+        if self.surfaces.is_empty() {
+            self.should_close = true;
+        }
+        if self
+            .cursor
+            .as_ref()
+            .is_some_and(|c| c.layer_surface.wl_surface() == layer.wl_surface())
+        {
+            self.cursor = None;
+        }
+        if self
+            .badge
+            .as_ref()
+            .is_some_and(|b| b.layer_surface.wl_surface() == layer.wl_surface())
+        {
+            self.badge = None;
+        }
     }
 
     fn configure(
         &mut self,
         _conn: &Connection,
-        qh: &QueueHandle<Self>,
-        _layer: &LayerSurface,
+        _qh: &QueueHandle<Self>,
+        layer: &LayerSurface,
         configure: LayerSurfaceConfigure,
         _serial: u32,
     ) {
-        self.width = configure.new_size.0;
-        self.height = configure.new_size.1;
-
-        if self.width == 0 || self.height == 0 {
-            self.width = 1920;
-            self.height = 1080;
+        if let Some(cursor) = &mut self.cursor
+            && cursor.layer_surface.wl_surface() == layer.wl_surface()
+        {
+            cursor.configured = true;
+            self.draw_cursor();
+            return;
+        }
+        if let Some(badge) = &mut self.badge
+            && badge.layer_surface.wl_surface() == layer.wl_surface()
+        {
+            badge.configured = true;
+            self.draw_badge();
+            return;
         }
 
-        self.configured = true;
+        let Some(surface) = self
+            .surfaces
+            .iter_mut()
+            .find(|s| s.layer_surface.wl_surface() == layer.wl_surface())
+        else {
+            return;
+        };
 
-        if self.hints.is_empty() {
-            self.hints = calculate_hints(
-                self.width,
-                self.height,
-                &self.config.hint_chars,
-                self.config.hint_size,
-            );
+        let (mut width, mut height) = configure.new_size;
+        if width == 0 || height == 0 {
+            width = 1920;
+            height = 1080;
         }
+        surface.rect.width = width;
+        surface.rect.height = height;
+        surface.configured = true;
 
-        self.draw(qh);
+        self.draw();
     }
 }
 
@@ -509,9 +1270,13 @@ delegate_registry!(OverlayApp);
 mod tests {
     use super::*;
 
+    fn single_screen(width: u32, height: u32) -> Vec<ScreenRect> {
+        vec![ScreenRect { x: 0, y: 0, width, height }]
+    }
+
     #[test]
     fn test_calculate_hints() {
-        let hints = calculate_hints(1920, 1080, "abcd", 20);
+        let hints = calculate_screen_hints(&single_screen(1920, 1080), "abcd", 20);
         assert_eq!(hints.len(), 16);
         assert_eq!(hints[0].label, "aa");
         assert_eq!(hints[1].label, "ab");
@@ -519,7 +1284,7 @@ mod tests {
 
     #[test]
     fn test_find_hint() {
-        let hints = calculate_hints(1920, 1080, "ab", 20);
+        let hints = calculate_screen_hints(&single_screen(1920, 1080), "ab", 20);
         let matches = find_hint_by_prefix(&hints, "a");
         assert_eq!(matches.len(), 2);
 
@@ -527,4 +1292,67 @@ mod tests {
         assert!(exact.is_some());
         assert_eq!(exact.unwrap().label, "ab");
     }
+
+    #[test]
+    fn test_multi_screen_hints() {
+        let screens = vec![
+            ScreenRect { x: 0, y: 0, width: 1920, height: 1080 },
+            ScreenRect { x: 1920, y: -200, width: 2560, height: 1440 },
+        ];
+        let hints = calculate_screen_hints(&screens, "abcd", 20);
+
+        // The 16 two-character labels are shared out by screen area
+        assert_eq!(hints.len(), 16);
+        assert!(hints.iter().all(|h| h.label.len() == 2));
+        assert_eq!(hints[0].label, "aa");
+
+        // Every label is unique and every point lies on its screen
+        let mut labels: Vec<_> = hints.iter().map(|h| h.label.as_str()).collect();
+        labels.sort();
+        labels.dedup();
+        assert_eq!(labels.len(), 16);
+        assert!(hints[..6].iter().all(|h| screens[0].contains(h.x, h.y)));
+        assert!(hints[6..].iter().all(|h| screens[1].contains(h.x, h.y)));
+    }
+
+    #[test]
+    fn test_hint_spacing_follows_hint_size() {
+        let chars = "abcdefghijklmnopqrstuvwxyz";
+        let hints = calculate_screen_hints(&single_screen(1920, 1080), chars, 20);
+        assert_eq!(hints.len(), 676);
+
+        // Bigger labels on a small output leave room for fewer points
+        let small = single_screen(1280, 720);
+        let hints = calculate_screen_hints(&small, chars, 40);
+        assert!(hints.len() < 676);
+        assert!(hints.iter().all(|h| small[0].contains(h.x, h.y)));
+        let (label_width, _) = label_size("aa", 40.0);
+        let mut xs: Vec<i32> = hints.iter().map(|h| h.x).collect();
+        xs.sort();
+        xs.dedup();
+        assert!(xs.windows(2).all(|w| w[1] - w[0] >= label_width + LABEL_PADDING * 4));
+    }
+
+    #[test]
+    fn test_split_by_area() {
+        let screens = vec![
+            ScreenRect { x: 0, y: 0, width: 1920, height: 1080 },
+            ScreenRect { x: 1920, y: 0, width: 1920, height: 1080 },
+            ScreenRect { x: 3840, y: 0, width: 3840, height: 2160 },
+        ];
+        assert_eq!(split_by_area(&screens, 676), vec![113, 113, 450]);
+        assert_eq!(split_by_area(&screens[..2], 5), vec![3, 2]);
+        assert_eq!(split_by_area(&[], 16), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_bounding_rect() {
+        let screens = vec![
+            ScreenRect { x: 0, y: 0, width: 1920, height: 1080 },
+            ScreenRect { x: 1920, y: -200, width: 2560, height: 1440 },
+        ];
+        let desktop = ScreenRect::bounding(&screens).unwrap();
+        assert_eq!(desktop, ScreenRect { x: 0, y: -200, width: 4480, height: 1440 });
+        assert!(ScreenRect::bounding(&[]).is_none());
+    }
 }
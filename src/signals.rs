@@ -0,0 +1,54 @@
+//! KWarpd Signal Handling
+//!
+//! Blocks the signals we care about and receives them through a signalfd, so
+//! they are handled in the main loop instead of in an async signal handler
+
+use anyhow::{Context, Result};
+use nix::sys::signal::{SigSet, Signal};
+use nix::sys::signalfd::{SfdFlags, SignalFd};
+use std::os::fd::{AsFd, BorrowedFd};
+
+/// Pending signals, readable from the event loop
+pub struct Signals {
+    fd: SignalFd,
+}
+
+impl Signals {
+    /// Block `signals` and route them to a new signalfd
+    pub fn new(signals: &[Signal]) -> Result<Self> {
+        let mut mask = SigSet::empty();
+        for &signal in signals {
+            mask.add(signal);
+        }
+        mask.thread_block().context("Failed to block signals")?;
+
+        let fd = SignalFd::with_flags(&mask, SfdFlags::SFD_NONBLOCK | SfdFlags::SFD_CLOEXEC)
+            .context("Failed to create signalfd")?;
+        Ok(Self { fd })
+    }
+
+    /// Read every signal delivered since the last call
+    pub fn pending(&self) -> Vec<Signal> {
+        let mut signals = Vec::new();
+        loop {
+            match self.fd.read_signal() {
+                Ok(Some(info)) => match Signal::try_from(info.ssi_signo as i32) {
+                    Ok(signal) => signals.push(signal),
+                    Err(_) => log::warn!("Received unknown signal {}", info.ssi_signo),
+                },
+                Ok(None) => break,
+                Err(e) => {
+                    log::warn!("Failed to read signal: {}", e);
+                    break;
+                }
+            }
+        }
+        signals
+    }
+}
+
+impl AsFd for Signals {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
@@ -0,0 +1,466 @@
+//! KWarpd Simulation
+//!
+//! Scripted key source, recording pointer and manual clock for driving whole
+//! sessions in tests, without keyboards, uinput or a compositor
+//!
+//! The harness calls the session's key and timer handlers directly, the way
+//! the event loop sources would, rather than dispatching the event loop: its
+//! timers run on the system clock, not the manual one.
+
+use anyhow::Result;
+use evdev::KeyCode;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::clock::Clock;
+use crate::config::{KeyBinding, MouseButton};
+use crate::input::{KeyEvent, KeySource, ModifierState};
+use crate::keymap::KeyTranslator;
+use crate::output::PointerBackend;
+
+/// Clock that only moves when told to
+#[derive(Clone)]
+pub struct ManualClock {
+    now: Rc<Cell<Instant>>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self {
+            now: Rc::new(Cell::new(Instant::now())),
+        }
+    }
+
+    /// Move to `at`, which must not be in the past
+    pub fn set(&self, at: Instant) {
+        assert!(at >= self.now.get(), "time went backwards");
+        self.now.set(at);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+}
+
+/// Key source that replays queued key presses and releases
+///
+/// Events get binding names and modifiers the way `InputManager` assigns
+/// them, using the physical key names.
+pub struct ScriptedKeys {
+    queue: Rc<RefCell<VecDeque<(KeyCode, bool)>>>,
+    grabbed: Rc<Cell<bool>>,
+    modifiers: ModifierState,
+    translator: KeyTranslator,
+}
+
+impl KeySource for ScriptedKeys {
+    fn grab(&mut self) -> Result<()> {
+        self.grabbed.set(true);
+        Ok(())
+    }
+
+    fn ungrab(&mut self) -> Result<()> {
+        self.grabbed.set(false);
+        self.modifiers = ModifierState::default();
+        Ok(())
+    }
+
+    fn is_grabbed(&self) -> bool {
+        self.grabbed.get()
+    }
+
+    fn poll_events(&mut self) -> Result<Vec<KeyEvent>> {
+        let mut events = Vec::new();
+        while let Some((code, pressed)) = self.queue.borrow_mut().pop_front() {
+            self.modifiers.update(code, pressed);
+            events.push(KeyEvent {
+                code,
+                key: self.translator.name(code).unwrap_or_default(),
+                pressed,
+                modifiers: self.modifiers.to_modifiers(),
+            });
+        }
+        Ok(events)
+    }
+
+    fn check_activation(&self, key: &str, binding: &KeyBinding) -> bool {
+        self.modifiers.matches(binding, key)
+    }
+
+    fn key_for_name(&self, name: &str) -> Option<KeyCode> {
+        self.translator.key_for_name(name)
+    }
+
+    fn set_translator(&mut self, translator: KeyTranslator) {
+        self.translator = translator;
+    }
+}
+
+/// Something a pointer backend was asked to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerEvent {
+    Move(i32, i32),
+    Warp(i32, i32),
+    Button(MouseButton, bool),
+    Scroll(i32, i32),
+}
+
+/// Pointer backend that records what it is asked to do
+pub struct RecordingPointer {
+    events: Rc<RefCell<Vec<PointerEvent>>>,
+}
+
+impl PointerBackend for RecordingPointer {
+    fn name(&self) -> &'static str {
+        "recording"
+    }
+
+    fn move_mouse(&mut self, dx: i32, dy: i32) -> Result<()> {
+        self.events.borrow_mut().push(PointerEvent::Move(dx, dy));
+        Ok(())
+    }
+
+    fn set_desktop_extent(&mut self, _x: i32, _y: i32, _width: u32, _height: u32) {}
+
+    fn warp_to(&mut self, x: i32, y: i32) -> Result<()> {
+        self.events.borrow_mut().push(PointerEvent::Warp(x, y));
+        Ok(())
+    }
+
+    fn button(&mut self, button: MouseButton, pressed: bool) -> Result<()> {
+        self.events.borrow_mut().push(PointerEvent::Button(button, pressed));
+        Ok(())
+    }
+
+    fn scroll(&mut self, dx: i32, dy: i32) -> Result<()> {
+        self.events.borrow_mut().push(PointerEvent::Scroll(dx, dy));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::output::Pointer;
+    use crate::overlay::calculate_screen_hints;
+    use crate::state::Mode;
+    use crate::{Session, FALLBACK_SCREEN};
+    use calloop::EventLoop;
+    use std::sync::Arc;
+
+    /// A session wired to scripted keys, a recording pointer and a manual clock
+    struct Harness {
+        session: Session,
+        clock: ManualClock,
+        keys: Rc<RefCell<VecDeque<(KeyCode, bool)>>>,
+        grabbed: Rc<Cell<bool>>,
+        events: Rc<RefCell<Vec<PointerEvent>>>,
+        translator: KeyTranslator,
+        /// Owner of the session's loop handle; never dispatched
+        _event_loop: EventLoop<'static, Session>,
+    }
+
+    impl Harness {
+        fn new(config: Config) -> Self {
+            let clock = ManualClock::new();
+            let keys = Rc::default();
+            let grabbed = Rc::default();
+            let events = Rc::default();
+            let input = ScriptedKeys {
+                queue: Rc::clone(&keys),
+                grabbed: Rc::clone(&grabbed),
+                modifiers: ModifierState::default(),
+                translator: KeyTranslator::physical(),
+            };
+            let pointer = Pointer::new(Box::new(RecordingPointer {
+                events: Rc::clone(&events),
+            }));
+            let event_loop = EventLoop::try_new().unwrap();
+            let session = Session::new(
+                Arc::new(config),
+                Box::new(input),
+                pointer,
+                event_loop.handle(),
+                Box::new(clock.clone()),
+            );
+            Self {
+                session,
+                clock,
+                keys,
+                grabbed,
+                events,
+                translator: KeyTranslator::physical(),
+                _event_loop: event_loop,
+            }
+        }
+
+        /// Deliver a key event the way the keyboard event source would, by
+        /// calling `read_input`
+        fn send(&mut self, code: KeyCode, pressed: bool) {
+            self.keys.borrow_mut().push_back((code, pressed));
+            self.session.read_input().unwrap();
+        }
+
+        fn key(&mut self, name: &str, pressed: bool) {
+            let code = self
+                .translator
+                .key_for_name(name)
+                .unwrap_or_else(|| panic!("no key named {}", name));
+            self.send(code, pressed);
+        }
+
+        fn press(&mut self, name: &str) {
+            self.key(name, true);
+        }
+
+        fn release(&mut self, name: &str) {
+            self.key(name, false);
+        }
+
+        fn tap(&mut self, name: &str) {
+            self.press(name);
+            self.release(name);
+        }
+
+        /// Press and release a binding like "A-M-c", modifiers first
+        fn chord(&mut self, binding: &str) {
+            let binding = KeyBinding::parse(binding).unwrap();
+            let mods = binding.modifiers;
+            let held: Vec<KeyCode> = [
+                (mods.alt, KeyCode::KEY_LEFTALT),
+                (mods.ctrl, KeyCode::KEY_LEFTCTRL),
+                (mods.shift, KeyCode::KEY_LEFTSHIFT),
+                (mods.super_key, KeyCode::KEY_LEFTMETA),
+            ]
+            .into_iter()
+            .filter_map(|(on, code)| on.then_some(code))
+            .collect();
+
+            for &code in &held {
+                self.send(code, true);
+            }
+            self.tap(&binding.key);
+            for &code in held.iter().rev() {
+                self.send(code, false);
+            }
+        }
+
+        /// Let `duration` pass, running `tick` for every deadline that falls
+        /// due on the way, as the timer source would
+        fn advance(&mut self, duration: Duration) {
+            let end = self.clock.now() + duration;
+            while let Some(at) = self.session.next_deadline()
+                && at <= end
+            {
+                self.clock.set(at.max(self.clock.now()));
+                self.session.tick().unwrap();
+            }
+            self.clock.set(end);
+        }
+
+        /// Take the pointer events recorded so far
+        fn take_events(&self) -> Vec<PointerEvent> {
+            std::mem::take(&mut self.events.borrow_mut())
+        }
+
+        /// Sum of the relative motion in `events`
+        fn total_motion(events: &[PointerEvent]) -> (i32, i32) {
+            events.iter().fold((0, 0), |(x, y), event| match event {
+                PointerEvent::Move(dx, dy) => (x + dx, y + dy),
+                _ => (x, y),
+            })
+        }
+    }
+
+    #[test]
+    fn test_activate_and_exit() {
+        let mut h = Harness::new(Config::default());
+
+        h.chord("A-M-c");
+        assert_eq!(h.session.state.mode, Mode::Normal);
+        assert!(h.grabbed.get());
+
+        h.tap("esc");
+        assert_eq!(h.session.state.mode, Mode::Inactive);
+        assert!(!h.grabbed.get());
+        assert!(h.take_events().is_empty());
+        assert_eq!(h.session.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_exit_with_esc_then_reactivate() {
+        let mut h = Harness::new(Config::default());
+
+        // Exiting on the Esc press means its release is never seen
+        h.chord("A-M-c");
+        h.tap("esc");
+        h.advance(Duration::from_secs(5));
+
+        h.chord("A-M-c");
+        h.advance(Duration::from_millis(20));
+        assert_eq!(h.session.state.mode, Mode::Normal);
+    }
+
+    #[test]
+    fn test_move_with_physics() {
+        let mut h = Harness::new(Config::default());
+        h.chord("A-M-c");
+
+        h.press("l");
+        h.advance(Duration::from_millis(500));
+        let held = h.take_events();
+        assert!(held.iter().all(|e| matches!(e, PointerEvent::Move(dx, 0) if *dx >= 0)));
+        let (dx, _) = Harness::total_motion(&held);
+        assert!(dx > 50, "moved only {} pixels", dx);
+
+        // The pointer coasts to a stop after the key is released
+        h.release("l");
+        h.advance(Duration::from_secs(1));
+        let coasting = h.take_events();
+        assert!(!coasting.is_empty());
+        assert!(coasting.iter().all(|e| matches!(e, PointerEvent::Move(dx, 0) if *dx >= 0)));
+        assert_eq!(h.session.next_deadline(), None);
+
+        h.advance(Duration::from_secs(1));
+        assert!(h.take_events().is_empty());
+    }
+
+    #[test]
+    fn test_drag() {
+        let mut h = Harness::new(Config::default());
+        h.chord("A-M-c");
+
+        h.tap("v");
+        assert_eq!(h.take_events(), vec![PointerEvent::Button(MouseButton::Left, true)]);
+
+        h.press("j");
+        h.advance(Duration::from_millis(300));
+        h.release("j");
+        let events = h.take_events();
+        let (dx, dy) = Harness::total_motion(&events);
+        assert_eq!(dx, 0);
+        assert!(dy > 0);
+        assert!(!events.iter().any(|e| matches!(e, PointerEvent::Button(..))));
+
+        h.tap("v");
+        assert_eq!(h.take_events(), vec![PointerEvent::Button(MouseButton::Left, false)]);
+
+        // Exiting lets go of a drag that is still held
+        h.tap("v");
+        h.tap("esc");
+        assert_eq!(
+            h.take_events(),
+            vec![
+                PointerEvent::Button(MouseButton::Left, true),
+                PointerEvent::Button(MouseButton::Left, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_click() {
+        let mut h = Harness::new(Config::default());
+        h.chord("A-M-c");
+
+        h.tap("m");
+        assert_eq!(
+            h.take_events(),
+            vec![
+                PointerEvent::Button(MouseButton::Left, true),
+                PointerEvent::Button(MouseButton::Left, false),
+            ]
+        );
+        assert_eq!(h.session.state.mode, Mode::Normal);
+    }
+
+    #[test]
+    fn test_hint_select() {
+        let config = Config::default();
+        let hints =
+            calculate_screen_hints(&[FALLBACK_SCREEN], &config.hint_chars, config.hint_size);
+        let target = &hints[hints.len() / 2];
+        let mut h = Harness::new(config);
+
+        h.chord("A-M-x");
+        assert_eq!(h.session.state.mode, Mode::Hint);
+
+        for ch in target.label.chars() {
+            h.tap(&ch.to_string());
+        }
+        assert_eq!(h.take_events(), vec![PointerEvent::Warp(target.x, target.y)]);
+        assert_eq!(h.session.state.mode, Mode::Inactive);
+        assert!(!h.grabbed.get());
+    }
+
+    #[test]
+    fn test_cursor_known_only_after_warp() {
+        let config = Config::default();
+        let hints =
+            calculate_screen_hints(&[FALLBACK_SCREEN], &config.hint_chars, config.hint_size);
+        let target = hints[0].clone();
+        let mut h = Harness::new(config);
+
+        // Unknown until kwarpd has put the pointer somewhere, and entering
+        // normal mode leaves the pointer where it is
+        h.chord("A-M-c");
+        assert!(h.take_events().is_empty());
+        h.tap("esc");
+        assert_eq!(h.session.cursor, None);
+
+        h.chord("A-M-x");
+        for ch in target.label.chars() {
+            h.tap(&ch.to_string());
+        }
+        assert_eq!(h.session.cursor, Some((target.x, target.y)));
+
+        // Fine-adjusting after a hint starts from it, with no further warp
+        h.chord("A-M-c");
+        assert_eq!(h.take_events(), vec![PointerEvent::Warp(target.x, target.y)]);
+
+        // Relative motion is never corrected, only forgotten
+        h.press("l");
+        h.advance(Duration::from_millis(300));
+        h.release("l");
+        h.advance(Duration::from_secs(1));
+        let events = h.take_events();
+        assert!(events.iter().all(|e| matches!(e, PointerEvent::Move(..))));
+        assert_eq!(h.session.cursor, None);
+    }
+
+    #[test]
+    fn test_copy_chord_with_unknown_key() {
+        let config = Config {
+            copy_chord: KeyBinding::parse("C-ä").unwrap(),
+            ..Config::default()
+        };
+        let mut h = Harness::new(config);
+        h.chord("A-M-c");
+
+        // The copy still exits instead of failing the session
+        h.tap("c");
+        assert_eq!(h.session.state.mode, Mode::Inactive);
+        assert!(h.session.running);
+    }
+
+    #[test]
+    fn test_idle_timeout() {
+        let config = Config {
+            idle_timeout: 5,
+            ..Config::default()
+        };
+        let mut h = Harness::new(config);
+        h.chord("A-M-c");
+
+        h.advance(Duration::from_secs(4));
+        assert_eq!(h.session.state.mode, Mode::Normal);
+
+        h.advance(Duration::from_secs(1));
+        assert_eq!(h.session.state.mode, Mode::Inactive);
+        assert!(!h.grabbed.get());
+    }
+}
@@ -2,7 +2,17 @@
 //!
 //! Defines the application modes and state transitions
 
-use crate::config::Config;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::config::{Config, MouseButton};
+
+/// Esc presses that force an exit when they land within `ESCAPE_WINDOW`
+const ESCAPE_PRESSES: usize = 5;
+const ESCAPE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Holding Esc this long forces an exit
+const ESCAPE_HOLD: Duration = Duration::from_secs(3);
 
 /// The current mode of the application
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -13,6 +23,20 @@ pub enum Mode {
     Normal,
     /// Hint mode - overlay is shown, waiting for hint input
     Hint,
+    /// Grid mode - screen is split into cells that narrow down on each key
+    Grid,
+}
+
+impl Mode {
+    /// Lowercase name used in status replies
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Inactive => "inactive",
+            Mode::Normal => "normal",
+            Mode::Hint => "hint",
+            Mode::Grid => "grid",
+        }
+    }
 }
 
 /// Actions that can be performed based on input
@@ -21,23 +45,30 @@ pub enum Action {
     /// No action
     None,
     /// Enter normal mode
+    #[allow(dead_code)]
     EnterNormal,
     /// Enter hint mode
     EnterHint,
+    /// Enter grid mode
+    EnterGrid,
     /// Exit to inactive state
     Exit,
     /// Move cursor in direction (dx, dy normalized)
     Move { dx: i32, dy: i32 },
-    /// Click a mouse button (0=left, 1=middle, 2=right)
-    Click(u8),
+    /// Click a mouse button
+    Click(MouseButton),
     /// Toggle drag mode
     ToggleDrag,
     /// Send copy key and exit
     CopyAndExit,
-    /// Scroll (dy: positive=down, negative=up)
-    Scroll(i32),
+    /// Scroll in direction (dx: positive=right, dy: positive=down)
+    Scroll { dx: i32, dy: i32 },
     /// Hint character typed
     HintChar(char),
+    /// Grid region changed (cell selected or selection undone)
+    GridSelect,
+    /// Warp to the centre of the current grid region
+    GridConfirm,
     /// Apply accelerator (multiply speed)
     Accelerate,
     /// Apply decelerator (reduce speed)
@@ -74,19 +105,128 @@ impl MovementState {
 /// Scroll direction state
 #[derive(Debug, Clone, Default)]
 pub struct ScrollState {
+    pub left: bool,
+    pub right: bool,
     pub up: bool,
     pub down: bool,
 }
 
 impl ScrollState {
-    /// Get scroll direction (-1 for up, 1 for down, 0 for none)
-    pub fn direction(&self) -> i32 {
-        if self.down { 1 } else if self.up { -1 } else { 0 }
+    /// Get scroll direction (dx: positive=right, dy: positive=down)
+    pub fn direction(&self) -> (i32, i32) {
+        let dx = if self.left { -1 } else { 0 } + if self.right { 1 } else { 0 };
+        let dy = if self.up { -1 } else { 0 } + if self.down { 1 } else { 0 };
+        (dx, dy)
     }
 
     /// Check if scrolling
     pub fn is_scrolling(&self) -> bool {
-        self.up || self.down
+        self.left || self.right || self.up || self.down
+    }
+}
+
+/// Area selected in grid mode, in global coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridRegion {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl GridRegion {
+    /// Get the cell at `index` (row-major) of a rows x cols split
+    pub fn cell(&self, index: usize, rows: u32, cols: u32) -> GridRegion {
+        let row = (index as u32 / cols) as f64;
+        let col = (index as u32 % cols) as f64;
+        let width = self.width / cols as f64;
+        let height = self.height / rows as f64;
+        GridRegion {
+            x: self.x + col * width,
+            y: self.y + row * height,
+            width,
+            height,
+        }
+    }
+
+    /// Get the centre point, rounded to whole pixels
+    pub fn center(&self) -> (i32, i32) {
+        (
+            (self.x + self.width / 2.0).round() as i32,
+            (self.y + self.height / 2.0).round() as i32,
+        )
+    }
+}
+
+/// Grid mode state: current region plus the regions it was narrowed from
+#[derive(Debug, Clone, Default)]
+pub struct GridState {
+    pub region: Option<GridRegion>,
+    pub history: Vec<GridRegion>,
+}
+
+impl GridState {
+    /// Number of cells selected so far
+    pub fn depth(&self) -> usize {
+        self.history.len()
+    }
+}
+
+/// Hard-coded way out of the keyboard grab
+///
+/// Works on the physical Esc key and ignores the config, so a broken binding
+/// or a stuck mode can never lock the user out of their keyboard.
+#[derive(Debug, Default)]
+pub struct EmergencyEscape {
+    presses: VecDeque<Instant>,
+    held_since: Option<Instant>,
+}
+
+impl EmergencyEscape {
+    /// Record an Esc press or release, returning true when the escape fires
+    pub fn key(&mut self, pressed: bool, now: Instant) -> bool {
+        if !pressed {
+            self.held_since = None;
+            return false;
+        }
+
+        self.held_since = Some(now);
+        self.presses.push_back(now);
+        while self
+            .presses
+            .front()
+            .is_some_and(|&t| now.duration_since(t) > ESCAPE_WINDOW)
+        {
+            self.presses.pop_front();
+        }
+
+        if self.presses.len() >= ESCAPE_PRESSES {
+            self.reset();
+            return true;
+        }
+        false
+    }
+
+    /// Check whether Esc has been held long enough, firing at most once per hold
+    pub fn held(&mut self, now: Instant) -> bool {
+        match self.held_since {
+            Some(since) if now.duration_since(since) >= ESCAPE_HOLD => {
+                self.reset();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Time at which the current Esc hold would fire, if Esc is held
+    pub fn hold_deadline(&self) -> Option<Instant> {
+        self.held_since.map(|since| since + ESCAPE_HOLD)
+    }
+
+    /// Forget presses seen so far
+    pub fn reset(&mut self) {
+        self.presses.clear();
+        self.held_since = None;
     }
 }
 
@@ -98,6 +238,7 @@ pub struct AppState {
     pub movement: MovementState,
     pub scroll: ScrollState,
     pub hint_buffer: String,
+    pub grid: GridState,
     pub current_speed: f64,
     pub current_scroll_speed: f64,
 }
@@ -110,6 +251,7 @@ impl Default for AppState {
             movement: MovementState::default(),
             scroll: ScrollState::default(),
             hint_buffer: String::new(),
+            grid: GridState::default(),
             current_speed: 0.0,
             current_scroll_speed: 0.0,
         }
@@ -126,6 +268,7 @@ impl AppState {
         self.movement = MovementState::default();
         self.scroll = ScrollState::default();
         self.hint_buffer.clear();
+        self.grid = GridState::default();
         self.current_speed = 0.0;
         self.current_scroll_speed = 0.0;
     }
@@ -142,6 +285,13 @@ impl AppState {
         self.mode = Mode::Hint;
     }
 
+    /// Enter grid mode covering the given region
+    pub fn enter_grid(&mut self, region: GridRegion) {
+        self.reset();
+        self.grid.region = Some(region);
+        self.mode = Mode::Grid;
+    }
+
     /// Exit to inactive
     pub fn exit(&mut self) {
         self.reset();
@@ -149,12 +299,30 @@ impl AppState {
         self.drag_active = false;
     }
 
+    /// Text for the mode badge, `None` while inactive
+    pub fn badge_text(&self) -> Option<String> {
+        if self.mode == Mode::Inactive {
+            return None;
+        }
+        let mut parts = vec![self.mode.name()];
+        if self.drag_active {
+            parts.push("drag");
+        }
+        if self.movement.accelerating {
+            parts.push("fast");
+        } else if self.movement.decelerating {
+            parts.push("slow");
+        }
+        Some(parts.join(" | "))
+    }
+
     /// Process a key and return the action
     pub fn process_key(&mut self, key: &str, pressed: bool, config: &Config) -> Action {
         match self.mode {
             Mode::Inactive => Action::None, // Activation handled elsewhere
             Mode::Normal => self.process_normal_key(key, pressed, config),
             Mode::Hint => self.process_hint_key(key, pressed, config),
+            Mode::Grid => self.process_grid_key(key, pressed, config),
         }
     }
 
@@ -176,6 +344,8 @@ impl AppState {
             if key == config.down { self.movement.down = false; }
             if key == config.scroll_up { self.scroll.up = false; }
             if key == config.scroll_down { self.scroll.down = false; }
+            if key == config.scroll_left { self.scroll.left = false; }
+            if key == config.scroll_right { self.scroll.right = false; }
             return Action::None;
         }
 
@@ -186,6 +356,9 @@ impl AppState {
         if key == config.hint {
             return Action::EnterHint;
         }
+        if key == config.grid {
+            return Action::EnterGrid;
+        }
         if key == config.drag {
             self.drag_active = !self.drag_active;
             return Action::ToggleDrag;
@@ -227,22 +400,28 @@ impl AppState {
         // Scroll keys
         if key == config.scroll_up && !self.scroll.up {
             self.scroll.up = true;
-            return Action::Scroll(-1);
+            let (dx, dy) = self.scroll.direction();
+            return Action::Scroll { dx, dy };
         }
         if key == config.scroll_down && !self.scroll.down {
             self.scroll.down = true;
-            return Action::Scroll(1);
+            let (dx, dy) = self.scroll.direction();
+            return Action::Scroll { dx, dy };
         }
-
-        // Mouse buttons
-        if key == config.buttons.left {
-            return Action::Click(0);
+        if key == config.scroll_left && !self.scroll.left {
+            self.scroll.left = true;
+            let (dx, dy) = self.scroll.direction();
+            return Action::Scroll { dx, dy };
         }
-        if key == config.buttons.middle {
-            return Action::Click(1);
+        if key == config.scroll_right && !self.scroll.right {
+            self.scroll.right = true;
+            let (dx, dy) = self.scroll.direction();
+            return Action::Scroll { dx, dy };
         }
-        if key == config.buttons.right {
-            return Action::Click(2);
+
+        // Mouse buttons
+        if let Some(button) = config.buttons.button_for(key) {
+            return Action::Click(button);
         }
 
         Action::None
@@ -273,6 +452,48 @@ impl AppState {
 
         Action::None
     }
+
+    fn process_grid_key(&mut self, key: &str, pressed: bool, config: &Config) -> Action {
+        if !pressed {
+            return Action::None;
+        }
+
+        if key == config.grid_exit || key == config.exit {
+            return Action::Exit;
+        }
+        if key == config.grid_confirm {
+            return Action::GridConfirm;
+        }
+
+        // Undo the last cell selection
+        if key == "backspace" {
+            if let Some(previous) = self.grid.history.pop() {
+                self.grid.region = Some(previous);
+                return Action::GridSelect;
+            }
+            return Action::None;
+        }
+
+        // Narrow the region to the selected cell
+        if key.len() == 1 {
+            let ch = key.chars().next().unwrap();
+            let cells = config.grid_rows as usize * config.grid_columns as usize;
+            let index = config.grid_keys.chars().take(cells).position(|c| c == ch);
+
+            if let (Some(index), Some(region)) = (index, self.grid.region) {
+                self.grid.history.push(region);
+                self.grid.region =
+                    Some(region.cell(index, config.grid_rows, config.grid_columns));
+
+                if self.grid.depth() >= config.grid_max_depth as usize {
+                    return Action::GridConfirm;
+                }
+                return Action::GridSelect;
+            }
+        }
+
+        Action::None
+    }
 }
 
 #[cfg(test)]
@@ -295,6 +516,20 @@ mod tests {
         assert_eq!(m.direction(), (0, -1));
     }
 
+    #[test]
+    fn test_scroll_keys() {
+        let config = Config::default();
+        let mut state = AppState::new();
+        state.enter_normal();
+
+        assert_eq!(state.process_key(&config.scroll_down, true, &config), Action::Scroll { dx: 0, dy: 1 });
+        assert_eq!(state.process_key(&config.scroll_right, true, &config), Action::Scroll { dx: 1, dy: 1 });
+        state.process_key(&config.scroll_down, false, &config);
+        assert_eq!(state.scroll.direction(), (1, 0));
+        state.process_key(&config.scroll_right, false, &config);
+        assert!(!state.scroll.is_scrolling());
+    }
+
     #[test]
     fn test_state_transitions() {
         let mut state = AppState::new();
@@ -309,4 +544,96 @@ mod tests {
         state.exit();
         assert_eq!(state.mode, Mode::Inactive);
     }
+
+    #[test]
+    fn test_badge_text() {
+        let config = Config::default();
+        let mut state = AppState::new();
+        assert_eq!(state.badge_text(), None);
+
+        state.enter_normal();
+        assert_eq!(state.badge_text().unwrap(), "normal");
+        state.process_key(&config.drag, true, &config);
+        state.process_key(&config.accelerator, true, &config);
+        assert_eq!(state.badge_text().unwrap(), "normal | drag | fast");
+        state.process_key(&config.accelerator, false, &config);
+        state.process_key(&config.decelerator, true, &config);
+        assert_eq!(state.badge_text().unwrap(), "normal | drag | slow");
+
+        state.enter_hint();
+        assert_eq!(state.badge_text().unwrap(), "hint | drag");
+    }
+
+    #[test]
+    fn test_grid_narrowing() {
+        let config = Config::default();
+        let mut state = AppState::new();
+        state.enter_grid(GridRegion { x: 0.0, y: 0.0, width: 1920.0, height: 1080.0 });
+        assert_eq!(state.mode, Mode::Grid);
+
+        // Default 2x2 grid keyed "uijk": "k" is the bottom-right cell
+        assert_eq!(state.process_key("k", true, &config), Action::GridSelect);
+        let region = state.grid.region.unwrap();
+        assert_eq!(region, GridRegion { x: 960.0, y: 540.0, width: 960.0, height: 540.0 });
+        assert_eq!(region.center(), (1440, 810));
+
+        // Backspace returns to the previous region
+        assert_eq!(state.process_key("backspace", true, &config), Action::GridSelect);
+        assert_eq!(state.grid.region.unwrap().center(), (960, 540));
+
+        assert_eq!(state.process_key(&config.grid_confirm, true, &config), Action::GridConfirm);
+    }
+
+    #[test]
+    fn test_grid_max_depth_confirms() {
+        let config = Config {
+            grid_max_depth: 2,
+            ..Config::default()
+        };
+        let mut state = AppState::new();
+        state.enter_grid(GridRegion { x: 0.0, y: 0.0, width: 1000.0, height: 1000.0 });
+
+        assert_eq!(state.process_key("u", true, &config), Action::GridSelect);
+        assert_eq!(state.process_key("u", true, &config), Action::GridConfirm);
+        assert_eq!(state.grid.region.unwrap().center(), (125, 125));
+    }
+
+    #[test]
+    fn test_emergency_escape_presses() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut escape = EmergencyEscape::default();
+
+        // Five presses spread over more than a second don't fire
+        for ms in [0, 300, 600, 900, 1200] {
+            assert!(!escape.key(true, at(ms)));
+            assert!(!escape.key(false, at(ms + 50)));
+        }
+
+        // Five presses within a second do
+        escape.reset();
+        for ms in [0, 150, 300, 450] {
+            assert!(!escape.key(true, at(ms)));
+            escape.key(false, at(ms + 50));
+        }
+        assert!(escape.key(true, at(600)));
+    }
+
+    #[test]
+    fn test_emergency_escape_hold() {
+        let start = Instant::now();
+        let mut escape = EmergencyEscape::default();
+
+        escape.key(true, start);
+        assert_eq!(escape.hold_deadline(), Some(start + Duration::from_secs(3)));
+        assert!(!escape.held(start + Duration::from_secs(2)));
+        assert!(escape.held(start + Duration::from_secs(3)));
+        // Fires once per hold
+        assert!(!escape.held(start + Duration::from_secs(4)));
+
+        // Releasing in time cancels it
+        escape.key(true, start);
+        escape.key(false, start + Duration::from_secs(1));
+        assert!(!escape.held(start + Duration::from_secs(5)));
+    }
 }
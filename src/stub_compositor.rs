@@ -0,0 +1,177 @@
+//! KWarpd Stub Compositor
+//!
+//! Minimal Wayland server for testing the pointer backends: it offers a
+//! single protocol global on a socket pair and logs the requests it receives
+
+use std::os::fd::OwnedFd;
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use wayland_backend::protocol::{Argument, Interface, Message};
+use wayland_backend::server::{
+    Backend, ClientData, ClientId, GlobalHandler, GlobalId, Handle, ObjectData, ObjectId,
+};
+use wayland_client::{Connection, EventQueue};
+
+/// Requests received by the stub compositor, as "name arg arg"
+type Log = Arc<Mutex<Vec<String>>>;
+
+/// Global the stub offers, and how to log requests made through it
+#[derive(Clone, Copy)]
+pub struct StubGlobal {
+    pub interface: &'static Interface,
+    pub version: u32,
+    /// Requests whose first argument is a timestamp, left out of the log
+    pub timed: &'static [&'static str],
+}
+
+struct Client;
+
+impl ClientData for Client {}
+
+struct RecordingGlobal {
+    log: Log,
+    global: StubGlobal,
+}
+
+impl GlobalHandler<()> for RecordingGlobal {
+    fn bind(
+        self: Arc<Self>,
+        _handle: &Handle,
+        _data: &mut (),
+        _client_id: ClientId,
+        _global_id: GlobalId,
+        _object_id: ObjectId,
+    ) -> Arc<dyn ObjectData<()>> {
+        Arc::new(Recorder {
+            log: self.log.clone(),
+            interface: self.global.interface,
+            timed: self.global.timed,
+        })
+    }
+}
+
+/// Logs the requests to one object, and records any object they create
+struct Recorder {
+    log: Log,
+    interface: &'static Interface,
+    timed: &'static [&'static str],
+}
+
+impl ObjectData<()> for Recorder {
+    fn request(
+        self: Arc<Self>,
+        _handle: &Handle,
+        _data: &mut (),
+        _client_id: ClientId,
+        msg: Message<ObjectId, OwnedFd>,
+    ) -> Option<Arc<dyn ObjectData<()>>> {
+        let desc = &self.interface.requests[msg.opcode as usize];
+        let skip = usize::from(self.timed.contains(&desc.name));
+        let mut line = desc.name.to_string();
+        for arg in msg.args.iter().skip(skip) {
+            let arg = match arg {
+                Argument::Uint(v) => v.to_string(),
+                Argument::Int(v) => v.to_string(),
+                Argument::Fixed(v) => (*v as f64 / 256.0).to_string(),
+                Argument::Str(Some(s)) => s.to_string_lossy().into_owned(),
+                Argument::NewId(_) => "new_id".to_string(),
+                Argument::Object(id) if id.is_null() => "null".to_string(),
+                other => format!("{:?}", other),
+            };
+            line.push(' ');
+            line.push_str(&arg);
+        }
+        self.log.lock().unwrap().push(line);
+
+        desc.child_interface.map(|interface| {
+            Arc::new(Recorder {
+                log: self.log.clone(),
+                interface,
+                timed: self.timed,
+            }) as Arc<dyn ObjectData<()>>
+        })
+    }
+
+    fn destroyed(
+        self: Arc<Self>,
+        _handle: &Handle,
+        _data: &mut (),
+        _client_id: ClientId,
+        _object_id: ObjectId,
+    ) {
+    }
+}
+
+/// Compositor stub offering at most one global, served from its own thread
+pub struct StubServer {
+    log: Log,
+    stop: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl StubServer {
+    /// Start the server, offering `global` if given, and return it with a
+    /// client connection to it
+    pub fn start(global: Option<StubGlobal>) -> (Self, Connection) {
+        let (server_stream, client_stream) = UnixStream::pair().unwrap();
+        let log = Log::default();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let mut backend = Backend::<()>::new().unwrap();
+        if let Some(global) = global {
+            backend.handle().create_global(
+                global.interface,
+                global.version,
+                Arc::new(RecordingGlobal {
+                    log: log.clone(),
+                    global,
+                }),
+            );
+        }
+        backend
+            .handle()
+            .insert_client(server_stream, Arc::new(Client))
+            .unwrap();
+
+        let thread = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let _ = backend.dispatch_all_clients(&mut ());
+                    let _ = backend.flush(None);
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+        });
+
+        let conn = Connection::from_socket(client_stream).unwrap();
+        let server = Self {
+            log,
+            stop,
+            thread: Some(thread),
+        };
+        (server, conn)
+    }
+
+    /// Take the requests received so far
+    pub fn take(&self) -> Vec<String> {
+        std::mem::take(&mut self.log.lock().unwrap())
+    }
+}
+
+impl Drop for StubServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap();
+        }
+    }
+}
+
+/// Wait until the stub has handled everything sent so far
+pub fn sync<D: 'static>(event_queue: &mut EventQueue<D>, state: &mut D) {
+    event_queue.roundtrip(state).unwrap();
+}
@@ -0,0 +1,287 @@
+//! KWarpd wlroots Virtual Pointer
+//!
+//! Pointer backend using the zwlr_virtual_pointer_v1 Wayland protocol, which
+//! needs no access to /dev/uinput
+
+use anyhow::{Context, Result};
+use std::time::Instant;
+use wayland_client::{
+    Connection, Dispatch, EventQueue, QueueHandle,
+    globals::{GlobalListContents, registry_queue_init},
+    protocol::{wl_pointer, wl_registry},
+};
+use wayland_protocols_wlr::virtual_pointer::v1::client::{
+    zwlr_virtual_pointer_manager_v1::ZwlrVirtualPointerManagerV1,
+    zwlr_virtual_pointer_v1::ZwlrVirtualPointerV1,
+};
+
+use crate::config::MouseButton;
+use crate::output::{
+    button_code, DesktopExtent, PointerBackend, WheelAccumulator, AXIS_VALUE_PER_NOTCH,
+    WHEEL_UNITS_PER_NOTCH,
+};
+
+/// Dispatch target; none of the objects we use send events
+struct State;
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(
+        _state: &mut Self,
+        _registry: &wl_registry::WlRegistry,
+        _event: wl_registry::Event,
+        _data: &GlobalListContents,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrVirtualPointerManagerV1, ()> for State {
+    fn event(
+        _state: &mut Self,
+        _manager: &ZwlrVirtualPointerManagerV1,
+        _event: <ZwlrVirtualPointerManagerV1 as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrVirtualPointerV1, ()> for State {
+    fn event(
+        _state: &mut Self,
+        _pointer: &ZwlrVirtualPointerV1,
+        _event: <ZwlrVirtualPointerV1 as wayland_client::Proxy>::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+/// Virtual pointer created by the compositor over its own Wayland connection
+pub struct WlrPointer {
+    event_queue: EventQueue<State>,
+    manager: ZwlrVirtualPointerManagerV1,
+    pointer: ZwlrVirtualPointerV1,
+    extent: Option<DesktopExtent>,
+    wheel: WheelAccumulator,
+    hwheel: WheelAccumulator,
+    /// Epoch of the millisecond timestamps sent with each event
+    start: Instant,
+}
+
+impl WlrPointer {
+    /// Connect to the compositor and create a virtual pointer on the default seat
+    pub fn new() -> Result<Self> {
+        let conn = Connection::connect_to_env().context("Failed to connect to Wayland")?;
+        Self::with_connection(&conn)
+    }
+
+    fn with_connection(conn: &Connection) -> Result<Self> {
+        let (globals, mut event_queue) =
+            registry_queue_init::<State>(conn).context("Failed to initialize Wayland registry")?;
+        let qh = event_queue.handle();
+
+        let manager: ZwlrVirtualPointerManagerV1 = globals
+            .bind(&qh, 1..=2, ())
+            .context("Compositor does not support zwlr_virtual_pointer_manager_v1")?;
+        let pointer = manager.create_virtual_pointer(None, &qh, ());
+
+        // Surface protocol errors now rather than on the first motion
+        event_queue
+            .roundtrip(&mut State)
+            .context("Failed to create virtual pointer")?;
+
+        log::info!("Created wlr virtual pointer");
+
+        Ok(Self {
+            event_queue,
+            manager,
+            pointer,
+            extent: None,
+            wheel: WheelAccumulator::default(),
+            hwheel: WheelAccumulator::default(),
+            start: Instant::now(),
+        })
+    }
+
+    /// Milliseconds since the pointer was created
+    fn time(&self) -> u32 {
+        self.start.elapsed().as_millis() as u32
+    }
+
+    /// End the current group of events and send it
+    fn frame(&mut self) -> Result<()> {
+        self.pointer.frame();
+        self.event_queue
+            .flush()
+            .context("Failed to flush Wayland connection")
+    }
+
+    /// Send scroll along one axis, with discrete steps once whole notches add up
+    fn axis(&mut self, axis: wl_pointer::Axis, units: i32, time: u32) {
+        let wheel = match axis {
+            wl_pointer::Axis::HorizontalScroll => &mut self.hwheel,
+            _ => &mut self.wheel,
+        };
+        let notches = wheel.add(units);
+        let value = units as f64 / WHEEL_UNITS_PER_NOTCH as f64 * AXIS_VALUE_PER_NOTCH;
+
+        self.pointer.axis_source(wl_pointer::AxisSource::Wheel);
+        if notches != 0 {
+            self.pointer.axis_discrete(time, axis, value, notches);
+        } else {
+            self.pointer.axis(time, axis, value);
+        }
+    }
+}
+
+impl PointerBackend for WlrPointer {
+    fn name(&self) -> &'static str {
+        "wlr"
+    }
+
+    fn move_mouse(&mut self, dx: i32, dy: i32) -> Result<()> {
+        self.pointer.motion(self.time(), dx as f64, dy as f64);
+        self.frame()
+    }
+
+    fn set_desktop_extent(&mut self, x: i32, y: i32, width: u32, height: u32) {
+        self.extent = Some(DesktopExtent { x, y, width, height });
+    }
+
+    /// Uses motion_absolute, which the compositor maps onto the whole layout
+    fn warp_to(&mut self, x: i32, y: i32) -> Result<()> {
+        let extent = self
+            .extent
+            .context("Desktop extent unknown, cannot warp pointer")?;
+        let (x, y) = extent.local(x, y);
+        self.pointer
+            .motion_absolute(self.time(), x, y, extent.width, extent.height);
+        self.frame()
+    }
+
+    fn button(&mut self, button: MouseButton, pressed: bool) -> Result<()> {
+        let state = if pressed {
+            wl_pointer::ButtonState::Pressed
+        } else {
+            wl_pointer::ButtonState::Released
+        };
+        self.pointer
+            .button(self.time(), button_code(button) as u32, state);
+        self.frame()
+    }
+
+    fn scroll(&mut self, dx: i32, dy: i32) -> Result<()> {
+        let time = self.time();
+        if dy != 0 {
+            self.axis(wl_pointer::Axis::VerticalScroll, dy, time);
+        }
+        if dx != 0 {
+            self.axis(wl_pointer::Axis::HorizontalScroll, dx, time);
+        }
+        self.frame()
+    }
+}
+
+impl Drop for WlrPointer {
+    fn drop(&mut self) {
+        self.pointer.destroy();
+        self.manager.destroy();
+        let _ = self.event_queue.flush();
+        log::info!("Destroyed wlr virtual pointer");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stub_compositor::{StubGlobal, StubServer};
+    use wayland_protocols_wlr::virtual_pointer::v1::client::__interfaces::ZWLR_VIRTUAL_POINTER_MANAGER_V1_INTERFACE;
+
+    /// The virtual pointer manager, if `offer` is set
+    fn manager(offer: bool) -> Option<StubGlobal> {
+        offer.then_some(StubGlobal {
+            interface: &ZWLR_VIRTUAL_POINTER_MANAGER_V1_INTERFACE,
+            version: 2,
+            timed: &["motion", "motion_absolute", "button", "axis", "axis_discrete"],
+        })
+    }
+
+    /// Wait until the stub has handled everything sent so far
+    fn sync(pointer: &mut WlrPointer) {
+        crate::stub_compositor::sync(&mut pointer.event_queue, &mut State);
+    }
+
+    #[test]
+    fn test_creates_pointer_on_connect() {
+        let (server, conn) = StubServer::start(manager(true));
+        let _pointer = WlrPointer::with_connection(&conn).unwrap();
+        assert_eq!(server.take(), vec!["create_virtual_pointer null new_id"]);
+    }
+
+    #[test]
+    fn test_pointer_requests() {
+        let (server, conn) = StubServer::start(manager(true));
+        let mut pointer = WlrPointer::with_connection(&conn).unwrap();
+        server.take();
+
+        pointer.move_mouse(5, -3).unwrap();
+        pointer.button(MouseButton::Left, true).unwrap();
+        pointer.button(MouseButton::Left, false).unwrap();
+        pointer.scroll(-60, 240).unwrap();
+        sync(&mut pointer);
+
+        assert_eq!(
+            server.take(),
+            vec![
+                "motion 5 -3",
+                "frame",
+                "button 272 1",
+                "frame",
+                "button 272 0",
+                "frame",
+                "axis_source 0",
+                "axis_discrete 0 30 2",
+                "axis_source 0",
+                "axis 1 -7.5",
+                "frame",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_warp_within_extent() {
+        let (server, conn) = StubServer::start(manager(true));
+        let mut pointer = WlrPointer::with_connection(&conn).unwrap();
+        server.take();
+
+        // Unknown until the overlay reports the outputs
+        assert!(pointer.warp_to(100, 100).is_err());
+
+        // A screen left of the origin: positions are relative to the layout's
+        // top left and clamped onto it
+        pointer.set_desktop_extent(-1920, -200, 4480, 1440);
+        pointer.warp_to(0, 540).unwrap();
+        pointer.warp_to(-5000, 5000).unwrap();
+        sync(&mut pointer);
+
+        assert_eq!(
+            server.take(),
+            vec![
+                "motion_absolute 1920 740 4480 1440",
+                "frame",
+                "motion_absolute 0 1439 4480 1440",
+                "frame",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_global() {
+        let (_server, conn) = StubServer::start(manager(false));
+        assert!(WlrPointer::with_connection(&conn).is_err());
+    }
+}